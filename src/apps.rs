@@ -0,0 +1,85 @@
+// src/apps.rs
+
+//! Applications for focus-or-launch: `focus-or-launch <name>` focuses the most recently
+//! used window of the named application on any desktop, or starts the application if it
+//! has no window. Bound to a key like any other command, this replaces the usual
+//! "run or raise" scripts.
+
+use serde::Deserialize;
+use std::{io, os::windows::process::CommandExt, process};
+
+use crate::command::DesktopTarget;
+use crate::rules::{Pattern, RuleDesktop, WindowMatcher};
+
+/// An application as written in the config file, e.g.
+/// `{ name = "browser", exe = "firefox.exe", command = '"C:\Program Files\Mozilla Firefox\firefox.exe"', desktop = 2 }`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "AppConfig")]
+pub struct AppSpec {
+    /// What `focus-or-launch` calls it.
+    pub name: String,
+    /// Which windows belong to it.
+    pub matcher: WindowMatcher,
+    /// The command line that starts it.
+    pub command: String,
+    /// Where to start it; the current desktop if none.
+    pub desktop: Option<DesktopTarget>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AppConfig {
+    name: String,
+    exe: Option<Pattern>,
+    class: Option<Pattern>,
+    title: Option<Pattern>,
+    command: String,
+    desktop: Option<RuleDesktop>,
+}
+
+impl TryFrom<AppConfig> for AppSpec {
+    type Error = String;
+
+    fn try_from(config: AppConfig) -> Result<Self, Self::Error> {
+        let matcher = WindowMatcher { exe: config.exe, class: config.class, title: config.title };
+        if matcher.is_empty() {
+            return Err(format!("app '{}' needs at least one of exe, class or title", config.name));
+        }
+        if split_command_line(&config.command).is_none() {
+            return Err(format!("app '{}' has an empty command", config.name));
+        }
+        let desktop = config.desktop.map(RuleDesktop::target).transpose()?;
+        Ok(AppSpec { name: config.name, matcher, command: config.command, desktop })
+    }
+}
+
+/// The app called `name`, ignoring case.
+pub fn find_app<'a>(apps: &'a [AppSpec], name: &str) -> Option<&'a AppSpec> {
+    apps.iter().find(|app| app.name.eq_ignore_ascii_case(name))
+}
+
+/// Splits a command line into the program and the rest, which is passed on as written.
+/// The program may be quoted to allow spaces in its path.
+pub fn split_command_line(command_line: &str) -> Option<(&str, &str)> {
+    let command_line = command_line.trim();
+    let (program, rest) = match command_line.strip_prefix('"') {
+        Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+        None => command_line.split_once(char::is_whitespace).unwrap_or((command_line, "")),
+    };
+    if program.is_empty() {
+        return None;
+    }
+    Some((program, rest.trim()))
+}
+
+/// Starts the app's command without waiting for it.
+pub fn launch(app: &AppSpec) -> io::Result<()> {
+    let (program, args) = split_command_line(&app.command)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+    let mut command = process::Command::new(program);
+    // Windows programs parse their own command line; pass the arguments on untouched.
+    if !args.is_empty() {
+        command.raw_arg(args);
+    }
+    command.spawn().map(|_| ())
+}
@@ -0,0 +1,120 @@
+// src/backend.rs
+
+//! Tracks whether the virtual desktop service is reachable. When explorer.exe crashes or
+//! restarts, every virtual desktop call fails until it is back; `BackendHealth` turns the
+//! first such failure into a single outage report and paces reconnection attempts with
+//! exponential backoff. The event loop makes the calls that probe the service; this only
+//! says when the next one is due and how long the outage lasted.
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BackendState {
+    Connected,
+    /// Unreachable since `since`, after `attempts` failed reconnection attempts.
+    Disconnected { since: Instant, attempts: u32 },
+}
+
+#[derive(Debug)]
+pub struct BackendHealth {
+    state: BackendState,
+    initial_delay: Duration,
+    max_delay: Duration,
+}
+
+impl BackendHealth {
+    pub fn new(initial_delay: Duration, max_delay: Duration) -> Self {
+        BackendHealth { state: BackendState::Connected, initial_delay, max_delay }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state == BackendState::Connected
+    }
+
+    /// Records that the service was found unreachable. Returns true if this starts a new
+    /// outage, which is then the only failure worth reporting until it ends.
+    pub fn mark_disconnected(&mut self, now: Instant) -> bool {
+        match self.state {
+            BackendState::Connected => {
+                self.state = BackendState::Disconnected { since: now, attempts: 0 };
+                true
+            }
+            BackendState::Disconnected { .. } => false,
+        }
+    }
+
+    /// Records that the service answered again. Returns how long the outage lasted, if
+    /// there was one.
+    pub fn mark_connected(&mut self, now: Instant) -> Option<Duration> {
+        match std::mem::replace(&mut self.state, BackendState::Connected) {
+            BackendState::Connected => None,
+            BackendState::Disconnected { since, .. } => Some(now.duration_since(since)),
+        }
+    }
+
+    /// Counts a failed reconnection attempt and returns how long to wait before the next
+    /// one: `initial_delay`, doubling per attempt up to `max_delay`.
+    pub fn next_retry_delay(&mut self) -> Duration {
+        let attempts = match &mut self.state {
+            BackendState::Connected => 0,
+            BackendState::Disconnected { attempts, .. } => {
+                let previous = *attempts;
+                *attempts = attempts.saturating_add(1);
+                previous
+            }
+        };
+        let factor = 1u32.checked_shl(attempts.min(31)).unwrap_or(u32::MAX);
+        self.initial_delay.saturating_mul(factor).min(self.max_delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INITIAL: Duration = Duration::from_millis(500);
+    const MAX: Duration = Duration::from_secs(30);
+
+    fn delays(health: &mut BackendHealth, count: usize) -> Vec<Duration> {
+        (0..count).map(|_| health.next_retry_delay()).collect()
+    }
+
+    #[test]
+    fn reports_an_outage_once() {
+        let start = Instant::now();
+        let mut health = BackendHealth::new(INITIAL, MAX);
+        assert!(health.is_connected());
+        assert!(health.mark_disconnected(start));
+        assert!(!health.mark_disconnected(start + Duration::from_secs(1)));
+        assert!(!health.is_connected());
+        assert_eq!(health.mark_connected(start + Duration::from_secs(5)), Some(Duration::from_secs(5)));
+        assert_eq!(health.mark_connected(start + Duration::from_secs(6)), None);
+        // A later outage is a new one.
+        assert!(health.mark_disconnected(start + Duration::from_secs(7)));
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_cap() {
+        let mut health = BackendHealth::new(INITIAL, MAX);
+        health.mark_disconnected(Instant::now());
+        let expected: Vec<Duration> = [500, 1000, 2000, 4000, 8000, 16000, 30000, 30000]
+            .into_iter()
+            .map(Duration::from_millis)
+            .collect();
+        assert_eq!(delays(&mut health, 8), expected);
+        // Many attempts later, the doubling neither overflows nor passes the cap.
+        assert!(delays(&mut health, 100).iter().all(|&delay| delay == MAX));
+    }
+
+    #[test]
+    fn starts_over_after_reconnecting() {
+        let start = Instant::now();
+        let mut health = BackendHealth::new(INITIAL, MAX);
+        health.mark_disconnected(start);
+        delays(&mut health, 4);
+        health.mark_connected(start + Duration::from_secs(10));
+        assert_eq!(health.next_retry_delay(), INITIAL);
+        health.mark_disconnected(start + Duration::from_secs(20));
+        assert_eq!(delays(&mut health, 2), [INITIAL, INITIAL * 2]);
+    }
+}
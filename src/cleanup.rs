@@ -0,0 +1,216 @@
+// src/cleanup.rs
+
+//! Removes desktops that DeskHopper created on demand once the user is done with them:
+//! when such a desktop isn't the current one, stays empty and isn't returned to within a
+//! grace period. That includes desktops created on the way to another one and never
+//! entered, such as 3 and 4 when switching from 2 to a new desktop 5.
+//! Desktops the user created are never touched. The collector tracks grace periods by
+//! desktop ID; whether a desktop still holds windows is only asked, through a callback,
+//! once `select_removals` picks what to remove.
+
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cleanup {
+    /// Keep every desktop.
+    Off,
+    /// Remove only desktops at the end of the list, so no other desktop changes its number.
+    Trailing,
+    /// Remove any created desktop that is empty.
+    Any,
+}
+
+#[derive(Debug)]
+pub struct DesktopCollector<Id> {
+    grace: Duration,
+    /// Desktops DeskHopper created.
+    created: HashSet<Id>,
+    /// Created desktops that stopped being the current one, or never were, and since when.
+    left: HashMap<Id, Instant>,
+}
+
+impl<Id: Copy + Eq + Hash> DesktopCollector<Id> {
+    pub fn new(grace: Duration) -> Self {
+        DesktopCollector { grace, created: HashSet::new(), left: HashMap::new() }
+    }
+
+    /// Records a desktop created on demand.
+    pub fn created(&mut self, id: Id) {
+        self.created.insert(id);
+    }
+
+    /// Records a switch from `old` to `new`: every created desktop other than `new` starts
+    /// its grace period unless it already has one running, and coming back to a desktop
+    /// cancels its grace period.
+    pub fn switched(&mut self, old: Id, new: Id, now: Instant) {
+        self.left.remove(&new);
+        if old == new {
+            return;
+        }
+        for id in self.created.iter().filter(|id| **id != new) {
+            self.left.entry(*id).or_insert(now);
+        }
+    }
+
+    /// The created desktops whose grace period is over.
+    pub fn due(&self, now: Instant) -> Vec<Id> {
+        self.left
+            .iter()
+            .filter(|(_, left_at)| now.duration_since(**left_at) >= self.grace)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// When the next grace period ends, if any desktop is waiting.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.left.values().map(|left_at| *left_at + self.grace).min()
+    }
+
+    /// Forgets a desktop that was removed.
+    pub fn removed(&mut self, id: Id) {
+        self.created.remove(&id);
+        self.left.remove(&id);
+    }
+
+    /// Stops waiting on a desktop that was due but kept, e.g. because it holds windows.
+    /// The next switch starts a new grace period.
+    pub fn kept(&mut self, id: Id) {
+        self.left.remove(&id);
+    }
+}
+
+/// Picks which `due` desktops to remove, last first. `desktops` lists every desktop in
+/// order; the current desktop and those `occupied` says hold windows are kept. With
+/// `Cleanup::Trailing`, removal stops at the first desktop from the end that is kept.
+pub fn select_removals<Id: Copy + Eq>(
+    due: &[Id],
+    desktops: &[Id],
+    current: Id,
+    mode: Cleanup,
+    mut occupied: impl FnMut(usize, Id) -> bool,
+) -> Vec<Id> {
+    if mode == Cleanup::Off {
+        return Vec::new();
+    }
+    let mut removals = Vec::new();
+    for (idx, id) in desktops.iter().enumerate().rev() {
+        let removable = due.contains(id) && *id != current && !occupied(idx, *id);
+        if removable {
+            removals.push(*id);
+        } else if mode == Cleanup::Trailing {
+            break;
+        }
+    }
+    removals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRACE: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn left_desktops_become_due_after_the_grace_period() {
+        let start = Instant::now();
+        let mut collector = DesktopCollector::new(GRACE);
+        collector.created(3);
+        collector.switched(1, 3, start);
+        assert_eq!(collector.next_deadline(), None);
+
+        collector.switched(3, 1, start);
+        assert_eq!(collector.next_deadline(), Some(start + GRACE));
+        assert_eq!(collector.due(start + GRACE / 2), Vec::<u32>::new());
+        assert_eq!(collector.due(start + GRACE), [3]);
+    }
+
+    #[test]
+    fn returning_cancels_the_grace_period() {
+        let start = Instant::now();
+        let mut collector = DesktopCollector::new(GRACE);
+        collector.created(3);
+        collector.switched(3, 1, start);
+        collector.switched(1, 3, start + GRACE / 2);
+        assert_eq!(collector.due(start + GRACE * 2), Vec::<u32>::new());
+        assert_eq!(collector.next_deadline(), None);
+    }
+
+    #[test]
+    fn desktops_the_user_created_are_never_due() {
+        let start = Instant::now();
+        let mut collector = DesktopCollector::new(GRACE);
+        collector.switched(2, 1, start);
+        collector.switched(1, 2, start);
+        assert_eq!(collector.due(start + GRACE), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn desktops_passed_on_the_way_are_due_with_the_target() {
+        let start = Instant::now();
+        let mut collector = DesktopCollector::new(GRACE);
+        // Switching from 2 to 5 created 3, 4 and 5; only 5 was entered.
+        for id in [3, 4, 5] {
+            collector.created(id);
+        }
+        collector.switched(2, 5, start);
+        let mut due = collector.due(start + GRACE);
+        due.sort();
+        assert_eq!(due, [3, 4]);
+        // Kept while 5 is current, as trailing cleanup stops there.
+        collector.kept(3);
+        collector.kept(4);
+
+        collector.switched(5, 2, start + GRACE * 2);
+        let mut due = collector.due(start + GRACE * 3);
+        due.sort();
+        assert_eq!(due, [3, 4, 5]);
+        let desktops = [1, 2, 3, 4, 5];
+        assert_eq!(select_removals(&due, &desktops, 2, Cleanup::Trailing, |_, _| false), [5, 4, 3]);
+    }
+
+    #[test]
+    fn running_grace_periods_are_not_restarted() {
+        let start = Instant::now();
+        let mut collector = DesktopCollector::new(GRACE);
+        collector.created(3);
+        collector.switched(3, 1, start);
+        collector.switched(1, 2, start + GRACE / 2);
+        assert_eq!(collector.due(start + GRACE), [3]);
+    }
+
+    #[test]
+    fn removed_desktops_are_forgotten() {
+        let start = Instant::now();
+        let mut collector = DesktopCollector::new(GRACE);
+        collector.created(3);
+        collector.switched(3, 1, start);
+        collector.removed(3);
+        collector.switched(1, 2, start);
+        assert_eq!(collector.due(start + GRACE), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn trailing_removal_stops_at_the_first_kept_desktop() {
+        let desktops = [1, 2, 3, 4, 5];
+        let due = [2, 4, 5];
+        assert_eq!(select_removals(&due, &desktops, 1, Cleanup::Trailing, |_, _| false), [5, 4]);
+        // 4 holds windows, so 2 stays too.
+        assert_eq!(select_removals(&due, &desktops, 1, Cleanup::Trailing, |idx, _| idx == 3), [5]);
+        assert_eq!(select_removals(&due, &desktops, 5, Cleanup::Trailing, |_, _| false), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn any_removal_skips_kept_desktops() {
+        let desktops = [1, 2, 3, 4, 5];
+        let due = [2, 4, 5];
+        assert_eq!(select_removals(&due, &desktops, 1, Cleanup::Any, |_, _| false), [5, 4, 2]);
+        assert_eq!(select_removals(&due, &desktops, 5, Cleanup::Any, |_, id| id == 4), [2]);
+        assert_eq!(select_removals(&due, &desktops, 1, Cleanup::Off, |_, _| false), Vec::<u32>::new());
+    }
+}
@@ -0,0 +1,598 @@
+// src/command.rs
+
+//! Every action DeskHopper can perform, independent of what triggered it. Hotkeys, the
+//! tray menu, IPC requests and window rules are all turned into a `Command` and handed to
+//! `dispatch`, which drives a `CommandHandler`. The handler is a trait so dispatching can
+//! be exercised without a tray, hotkeys or virtual desktops.
+
+use log::info;
+use std::{fmt, str::FromStr};
+
+use crate::error::DeskError;
+
+/// The desktop an action applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DesktopTarget {
+    /// A 0-based desktop index. Missing desktops up to it are created.
+    Index(usize),
+    /// A new desktop appended after the last one.
+    New,
+    /// The desktop with this name, ignoring case.
+    Name(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+}
+
+/// Whether a window or app should be shown on every desktop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinChange {
+    Pin,
+    Unpin,
+    Toggle,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    SwitchDesktop(DesktopTarget),
+    /// Moves the active window, i.e. the last application window that had the foreground.
+    MoveActiveWindow(DesktopTarget),
+    /// Moves the active window and switches to the desktop it was moved to.
+    MoveActiveWindowAndFollow(DesktopTarget),
+    /// Creates an empty desktop at the position; later desktops move up one place.
+    CreateDesktop(DesktopTarget),
+    /// Removes the current desktop; its windows go to the desktop next to it.
+    RemoveDesktop,
+    /// Exchanges the windows and names of two desktops.
+    SwapDesktops(DesktopTarget, DesktopTarget),
+    /// Names the current desktop; an empty name removes its name.
+    RenameDesktop(String),
+    /// Reads the layout from the config file again and makes the desktops match it.
+    ApplyLayout,
+    /// Moves the current desktop one place in the order.
+    MoveDesktop(Direction),
+    /// Shows the active window on every desktop, or only on its own again.
+    PinActiveWindow(PinChange),
+    /// Like `PinActiveWindow`, for every window of the active window's application.
+    PinActiveApp(PinChange),
+    /// Reports every window with its handle, desktop, executable and title.
+    ListWindows,
+    /// Switches to the desktop of the window that best matches the query and focuses it.
+    FocusWindow(String),
+    /// Focuses a window of the app with this name from the config file, or starts the app.
+    FocusOrLaunch(String),
+    /// Reports the windows on desktops other than the current one, with their handles.
+    ListWindowsElsewhere,
+    /// Moves the window with this handle to the current desktop and focuses it.
+    BringWindow(isize),
+    /// Moves the window with this handle, as window rules do.
+    MoveWindow(isize, DesktopTarget),
+    /// Moves the window with this handle and switches to the desktop it was moved to.
+    MoveWindowAndFollow(isize, DesktopTarget),
+    /// Like `PinActiveWindow`, for the window with this handle.
+    PinWindow(isize, PinChange),
+    PauseHotkeys,
+    ResumeHotkeys,
+    TogglePauseHotkeys,
+    /// Reports which hotkeys are registered and why the others aren't.
+    ShowHotkeyStatus,
+    /// Enters the modal keymap, where plain keys trigger desktop commands.
+    EnterMode,
+    ExitMode,
+    /// Reports the active mode, or "default" outside of one.
+    ShowMode,
+    ShowAbout,
+    Exit,
+}
+
+/// Where a command came from. Only used for logging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandSource {
+    Hotkey,
+    TrayMenu,
+    Ipc,
+    /// A window rule, placing a newly shown window.
+    Rule,
+}
+
+/// Carries out the effects of commands.
+pub trait CommandHandler {
+    fn switch_desktop(&mut self, target: DesktopTarget) -> Result<(), DeskError>;
+    fn move_active_window(&mut self, target: DesktopTarget, follow: bool) -> Result<(), DeskError>;
+    fn create_desktop(&mut self, position: DesktopTarget) -> Result<(), DeskError>;
+    fn remove_desktop(&mut self) -> Result<(), DeskError>;
+    fn swap_desktops(&mut self, a: DesktopTarget, b: DesktopTarget) -> Result<(), DeskError>;
+    fn rename_desktop(&mut self, name: String) -> Result<(), DeskError>;
+    fn apply_layout(&mut self) -> Result<(), DeskError>;
+    fn move_desktop(&mut self, direction: Direction) -> Result<(), DeskError>;
+    fn pin_active_window(&mut self, change: PinChange, whole_app: bool) -> Result<(), DeskError>;
+    fn list_windows(&mut self) -> Result<(), DeskError>;
+    fn focus_window(&mut self, query: String) -> Result<(), DeskError>;
+    fn focus_or_launch(&mut self, app: String) -> Result<(), DeskError>;
+    fn list_windows_elsewhere(&mut self) -> Result<(), DeskError>;
+    fn bring_window(&mut self, hwnd: isize) -> Result<(), DeskError>;
+    fn move_window(&mut self, hwnd: isize, target: DesktopTarget, follow: bool) -> Result<(), DeskError>;
+    fn pin_window(&mut self, hwnd: isize, change: PinChange) -> Result<(), DeskError>;
+    fn hotkeys_paused(&self) -> bool;
+    fn set_hotkeys_paused(&mut self, paused: bool);
+    fn hotkey_report(&self) -> String;
+    fn enter_mode(&mut self);
+    fn exit_mode(&mut self);
+    fn current_mode(&self) -> Option<&str>;
+    fn show_about(&mut self);
+    fn exit(&mut self);
+}
+
+/// Carries out `command`, returning its output for commands that report something. The
+/// caller decides how output and failures are shown, as that depends on where the command
+/// came from.
+pub fn dispatch(
+    command: Command,
+    source: CommandSource,
+    handler: &mut impl CommandHandler,
+) -> Result<Option<String>, DeskError> {
+    info!("Dispatching {:?} from {:?}", command, source);
+    match command {
+        Command::SwitchDesktop(target) => handler.switch_desktop(target)?,
+        Command::MoveActiveWindow(target) => handler.move_active_window(target, false)?,
+        Command::MoveActiveWindowAndFollow(target) => handler.move_active_window(target, true)?,
+        Command::CreateDesktop(position) => handler.create_desktop(position)?,
+        Command::RemoveDesktop => handler.remove_desktop()?,
+        Command::SwapDesktops(a, b) => handler.swap_desktops(a, b)?,
+        Command::RenameDesktop(name) => handler.rename_desktop(name)?,
+        Command::ApplyLayout => handler.apply_layout()?,
+        Command::MoveDesktop(direction) => handler.move_desktop(direction)?,
+        Command::PinActiveWindow(change) => handler.pin_active_window(change, false)?,
+        Command::PinActiveApp(change) => handler.pin_active_window(change, true)?,
+        Command::ListWindows => handler.list_windows()?,
+        Command::FocusWindow(query) => handler.focus_window(query)?,
+        Command::FocusOrLaunch(app) => handler.focus_or_launch(app)?,
+        Command::ListWindowsElsewhere => handler.list_windows_elsewhere()?,
+        Command::BringWindow(hwnd) => handler.bring_window(hwnd)?,
+        Command::MoveWindow(hwnd, target) => handler.move_window(hwnd, target, false)?,
+        Command::MoveWindowAndFollow(hwnd, target) => handler.move_window(hwnd, target, true)?,
+        Command::PinWindow(hwnd, change) => handler.pin_window(hwnd, change)?,
+        Command::PauseHotkeys => handler.set_hotkeys_paused(true),
+        Command::ResumeHotkeys => handler.set_hotkeys_paused(false),
+        Command::TogglePauseHotkeys => {
+            let paused = handler.hotkeys_paused();
+            handler.set_hotkeys_paused(!paused);
+        }
+        Command::ShowHotkeyStatus => return Ok(Some(handler.hotkey_report())),
+        Command::EnterMode => handler.enter_mode(),
+        Command::ExitMode => handler.exit_mode(),
+        Command::ShowMode => return Ok(Some(handler.current_mode().unwrap_or("default").to_string())),
+        Command::ShowAbout => handler.show_about(),
+        Command::Exit => handler.exit(),
+    }
+    Ok(None)
+}
+
+impl fmt::Display for DesktopTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DesktopTarget::Index(idx) => write!(f, "{}", idx + 1),
+            DesktopTarget::New => write!(f, "new"),
+            DesktopTarget::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl FromStr for DesktopTarget {
+    type Err = String;
+
+    /// Parses a 1-based desktop number, as shown to users, "new", or else a desktop name.
+    /// A desktop named like a number or "new" can't be referred to by its name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("new") {
+            return Ok(DesktopTarget::New);
+        }
+        match s.parse::<usize>() {
+            Ok(number) if number >= 1 => Ok(DesktopTarget::Index(number - 1)),
+            Ok(_) => Err(format!("invalid desktop '{}', expected a number from 1, a name or 'new'", s)),
+            Err(_) => Ok(DesktopTarget::Name(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Left => write!(f, "left"),
+            Direction::Right => write!(f, "right"),
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "left" => Ok(Direction::Left),
+            "right" => Ok(Direction::Right),
+            _ => Err(format!("invalid direction '{}', expected 'left' or 'right'", s)),
+        }
+    }
+}
+
+// Parses a window handle as listed by `bring`: hexadecimal with a leading "0x", or decimal.
+fn parse_window_handle(s: &str) -> Result<isize, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => isize::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("invalid window handle '{}', expected e.g. 0x1a2b", s))
+}
+
+// Parses a desktop number or name that must refer to an existing desktop, unlike "new".
+fn parse_existing_desktop(s: &str) -> Result<DesktopTarget, String> {
+    match s.parse::<DesktopTarget>()? {
+        DesktopTarget::New => Err("expected a desktop number or name, not 'new'".to_string()),
+        target => Ok(target),
+    }
+}
+
+/// Textual form used by IPC and the command line, e.g. `switch 3` or `move new`.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::SwitchDesktop(target) => write!(f, "switch {}", target),
+            Command::MoveActiveWindow(target) => write!(f, "move {}", target),
+            Command::MoveActiveWindowAndFollow(target) => write!(f, "move-follow {}", target),
+            Command::CreateDesktop(position) => write!(f, "create {}", position),
+            Command::RemoveDesktop => write!(f, "remove"),
+            Command::SwapDesktops(a, b) => write!(f, "swap {} {}", a, b),
+            Command::RenameDesktop(name) if name.is_empty() => write!(f, "rename"),
+            Command::RenameDesktop(name) => write!(f, "rename {}", name),
+            Command::ApplyLayout => write!(f, "apply-layout"),
+            Command::MoveDesktop(direction) => write!(f, "move-desktop {}", direction),
+            Command::PinActiveWindow(PinChange::Pin) => write!(f, "pin"),
+            Command::PinActiveWindow(PinChange::Unpin) => write!(f, "unpin"),
+            Command::PinActiveWindow(PinChange::Toggle) => write!(f, "toggle-pin"),
+            Command::PinActiveApp(PinChange::Pin) => write!(f, "pin-app"),
+            Command::PinActiveApp(PinChange::Unpin) => write!(f, "unpin-app"),
+            Command::PinActiveApp(PinChange::Toggle) => write!(f, "toggle-pin-app"),
+            Command::ListWindows => write!(f, "windows"),
+            Command::FocusWindow(query) => write!(f, "focus {}", query),
+            Command::FocusOrLaunch(app) => write!(f, "focus-or-launch {}", app),
+            Command::ListWindowsElsewhere => write!(f, "bring"),
+            Command::BringWindow(hwnd) => write!(f, "bring {:#x}", hwnd),
+            Command::MoveWindow(hwnd, target) => write!(f, "move-window {:#x} {}", hwnd, target),
+            Command::MoveWindowAndFollow(hwnd, target) => write!(f, "move-window-follow {:#x} {}", hwnd, target),
+            Command::PinWindow(hwnd, PinChange::Pin) => write!(f, "pin-window {:#x}", hwnd),
+            Command::PinWindow(hwnd, PinChange::Unpin) => write!(f, "unpin-window {:#x}", hwnd),
+            Command::PinWindow(hwnd, PinChange::Toggle) => write!(f, "toggle-pin-window {:#x}", hwnd),
+            Command::PauseHotkeys => write!(f, "pause"),
+            Command::ResumeHotkeys => write!(f, "resume"),
+            Command::TogglePauseHotkeys => write!(f, "toggle-pause"),
+            Command::ShowHotkeyStatus => write!(f, "hotkeys"),
+            Command::EnterMode => write!(f, "enter-mode"),
+            Command::ExitMode => write!(f, "exit-mode"),
+            Command::ShowMode => write!(f, "mode"),
+            Command::ShowAbout => write!(f, "about"),
+            Command::Exit => write!(f, "exit"),
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let command = match words.as_slice() {
+            ["switch", target] => Command::SwitchDesktop(target.parse()?),
+            ["move", target] => Command::MoveActiveWindow(target.parse()?),
+            ["move-follow", target] => Command::MoveActiveWindowAndFollow(target.parse()?),
+            ["create", position] => Command::CreateDesktop(position.parse()?),
+            ["remove"] => Command::RemoveDesktop,
+            ["swap", a, b] => Command::SwapDesktops(parse_existing_desktop(a)?, parse_existing_desktop(b)?),
+            ["rename", name @ ..] => Command::RenameDesktop(name.join(" ")),
+            ["apply-layout"] => Command::ApplyLayout,
+            ["move-desktop", direction] => Command::MoveDesktop(direction.parse()?),
+            ["pin"] => Command::PinActiveWindow(PinChange::Pin),
+            ["unpin"] => Command::PinActiveWindow(PinChange::Unpin),
+            ["toggle-pin"] => Command::PinActiveWindow(PinChange::Toggle),
+            ["pin-app"] => Command::PinActiveApp(PinChange::Pin),
+            ["unpin-app"] => Command::PinActiveApp(PinChange::Unpin),
+            ["toggle-pin-app"] => Command::PinActiveApp(PinChange::Toggle),
+            ["windows"] => Command::ListWindows,
+            ["focus", query @ ..] if !query.is_empty() => Command::FocusWindow(query.join(" ")),
+            ["focus-or-launch", app @ ..] if !app.is_empty() => Command::FocusOrLaunch(app.join(" ")),
+            ["bring"] => Command::ListWindowsElsewhere,
+            ["bring", hwnd] => Command::BringWindow(parse_window_handle(hwnd)?),
+            ["move-window", hwnd, target] => Command::MoveWindow(parse_window_handle(hwnd)?, target.parse()?),
+            ["move-window-follow", hwnd, target] => {
+                Command::MoveWindowAndFollow(parse_window_handle(hwnd)?, target.parse()?)
+            }
+            ["pin-window", hwnd] => Command::PinWindow(parse_window_handle(hwnd)?, PinChange::Pin),
+            ["unpin-window", hwnd] => Command::PinWindow(parse_window_handle(hwnd)?, PinChange::Unpin),
+            ["toggle-pin-window", hwnd] => Command::PinWindow(parse_window_handle(hwnd)?, PinChange::Toggle),
+            ["pause"] => Command::PauseHotkeys,
+            ["resume"] => Command::ResumeHotkeys,
+            ["toggle-pause"] => Command::TogglePauseHotkeys,
+            ["hotkeys"] => Command::ShowHotkeyStatus,
+            ["enter-mode"] => Command::EnterMode,
+            ["exit-mode"] => Command::ExitMode,
+            ["mode"] => Command::ShowMode,
+            ["about"] => Command::ShowAbout,
+            ["exit"] => Command::Exit,
+            [] => return Err("empty command".to_string()),
+            _ => return Err(format!("unknown command '{}'", s.trim())),
+        };
+        Ok(command)
+    }
+}
+
+/// Usage text for the command line client.
+pub const COMMAND_USAGE: &str = "\
+Commands:
+  switch <N|name|new>
+                   Switch to desktop N (1-based) or the desktop with that name, or
+                   to a new desktop
+  move <N|name|new>
+                   Move the active window to desktop N, or to a new desktop
+  move-follow <N|name|new>
+                   Move the active window to desktop N and switch there
+  create <N|name|new>
+                   Create an empty desktop at position N, or after the last one
+  remove           Remove the current desktop; its windows go to a neighbour
+  swap <A> <B>     Exchange the windows and names of desktops A and B
+  rename [name]    Name the current desktop, or clear its name
+  apply-layout [--dry-run]
+                   Make the desktops match the layout in the config file, or only
+                   list the changes that would take
+  move-desktop <left|right>
+                   Move the current desktop one place in the order
+  pin | unpin | toggle-pin
+                   Show the active window on every desktop, or only on its own
+  pin-app | unpin-app | toggle-pin-app
+                   The same for every window of the active window's application
+  windows          List every window: handle, desktop, executable and title
+  focus <query>    Switch to the window that best matches the query, e.g. 'focus
+                   fire inbox', or has that handle, and focus it
+  focus-or-launch <app>
+                   Focus a window of the app from the config file, on any desktop,
+                   or start the app if it has none
+  menu [--list|--run]
+                   List desktops and windows for a launcher, then read the chosen
+                   line from stdin and carry it out; or do only one of the two
+  bring [window]   List the windows on other desktops, or move the window with that
+                   handle (as listed) to the current desktop and focus it
+  move-window <window> <N|name|new>
+                   Move the window with that handle to desktop N
+  move-window-follow <window> <N|name|new>
+                   Move the window with that handle to desktop N and switch there
+  pin-window | unpin-window | toggle-pin-window <window>
+                   Show the window with that handle on every desktop, or only on its own
+  pause            Pause all hotkeys
+  resume           Resume hotkeys
+  toggle-pause     Pause or resume hotkeys
+  hotkeys          List hotkeys and whether each one could be registered
+  enter-mode       Enter desktop mode, where plain keys switch and move
+  exit-mode        Leave desktop mode
+  mode             Show the active mode, or 'default'
+  about            Show the About dialog
+  exit             Quit DeskHopper";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Records the calls dispatch makes instead of carrying them out.
+    #[derive(Default)]
+    struct Recorder {
+        calls: Vec<String>,
+        paused: bool,
+        mode: Option<String>,
+        fail_with: Option<DeskError>,
+    }
+
+    impl Recorder {
+        fn record(&mut self, call: String) -> Result<(), DeskError> {
+            self.calls.push(call);
+            self.fail_with.clone().map_or(Ok(()), Err)
+        }
+    }
+
+    impl CommandHandler for Recorder {
+        fn switch_desktop(&mut self, target: DesktopTarget) -> Result<(), DeskError> {
+            self.record(format!("switch_desktop {:?}", target))
+        }
+        fn move_active_window(&mut self, target: DesktopTarget, follow: bool) -> Result<(), DeskError> {
+            self.record(format!("move_active_window {:?} {}", target, follow))
+        }
+        fn create_desktop(&mut self, position: DesktopTarget) -> Result<(), DeskError> {
+            self.record(format!("create_desktop {:?}", position))
+        }
+        fn remove_desktop(&mut self) -> Result<(), DeskError> {
+            self.record("remove_desktop".to_string())
+        }
+        fn swap_desktops(&mut self, a: DesktopTarget, b: DesktopTarget) -> Result<(), DeskError> {
+            self.record(format!("swap_desktops {:?} {:?}", a, b))
+        }
+        fn rename_desktop(&mut self, name: String) -> Result<(), DeskError> {
+            self.record(format!("rename_desktop {:?}", name))
+        }
+        fn apply_layout(&mut self) -> Result<(), DeskError> {
+            self.record("apply_layout".to_string())
+        }
+        fn move_desktop(&mut self, direction: Direction) -> Result<(), DeskError> {
+            self.record(format!("move_desktop {:?}", direction))
+        }
+        fn pin_active_window(&mut self, change: PinChange, whole_app: bool) -> Result<(), DeskError> {
+            self.record(format!("pin_active_window {:?} {}", change, whole_app))
+        }
+        fn list_windows(&mut self) -> Result<(), DeskError> {
+            self.record("list_windows".to_string())
+        }
+        fn focus_window(&mut self, query: String) -> Result<(), DeskError> {
+            self.record(format!("focus_window {:?}", query))
+        }
+        fn focus_or_launch(&mut self, app: String) -> Result<(), DeskError> {
+            self.record(format!("focus_or_launch {:?}", app))
+        }
+        fn list_windows_elsewhere(&mut self) -> Result<(), DeskError> {
+            self.record("list_windows_elsewhere".to_string())
+        }
+        fn bring_window(&mut self, hwnd: isize) -> Result<(), DeskError> {
+            self.record(format!("bring_window {:#x}", hwnd))
+        }
+        fn move_window(&mut self, hwnd: isize, target: DesktopTarget, follow: bool) -> Result<(), DeskError> {
+            self.record(format!("move_window {:#x} {:?} {}", hwnd, target, follow))
+        }
+        fn pin_window(&mut self, hwnd: isize, change: PinChange) -> Result<(), DeskError> {
+            self.record(format!("pin_window {:#x} {:?}", hwnd, change))
+        }
+        fn hotkeys_paused(&self) -> bool {
+            self.paused
+        }
+        fn set_hotkeys_paused(&mut self, paused: bool) {
+            self.calls.push(format!("set_hotkeys_paused {}", paused));
+            self.paused = paused;
+        }
+        fn hotkey_report(&self) -> String {
+            "all registered".to_string()
+        }
+        fn enter_mode(&mut self) {
+            self.calls.push("enter_mode".to_string());
+            self.mode = Some("desktop".to_string());
+        }
+        fn exit_mode(&mut self) {
+            self.calls.push("exit_mode".to_string());
+            self.mode = None;
+        }
+        fn current_mode(&self) -> Option<&str> {
+            self.mode.as_deref()
+        }
+        fn show_about(&mut self) {
+            self.calls.push("show_about".to_string());
+        }
+        fn exit(&mut self) {
+            self.calls.push("exit".to_string());
+        }
+    }
+
+    fn run(handler: &mut Recorder, command: &str) -> Result<Option<String>, DeskError> {
+        dispatch(command.parse().unwrap(), CommandSource::Ipc, handler)
+    }
+
+    #[test]
+    fn routes_commands_to_the_handler() {
+        let cases = [
+            ("switch 3", "switch_desktop Index(2)"),
+            ("switch mail", "switch_desktop Name(\"mail\")"),
+            ("move new", "move_active_window New false"),
+            ("move-follow 2", "move_active_window Index(1) true"),
+            ("create 1", "create_desktop Index(0)"),
+            ("remove", "remove_desktop"),
+            ("swap 1 chat", "swap_desktops Index(0) Name(\"chat\")"),
+            ("rename Web dev", "rename_desktop \"Web dev\""),
+            ("apply-layout", "apply_layout"),
+            ("move-desktop left", "move_desktop Left"),
+            ("toggle-pin", "pin_active_window Toggle false"),
+            ("unpin-app", "pin_active_window Unpin true"),
+            ("windows", "list_windows"),
+            ("focus fire inbox", "focus_window \"fire inbox\""),
+            ("focus-or-launch mail", "focus_or_launch \"mail\""),
+            ("bring", "list_windows_elsewhere"),
+            ("bring 0x1a2b", "bring_window 0x1a2b"),
+            ("move-window 0x1a2b 2", "move_window 0x1a2b Index(1) false"),
+            ("move-window-follow 0x1a2b mail", "move_window 0x1a2b Name(\"mail\") true"),
+            ("toggle-pin-window 0x1a2b", "pin_window 0x1a2b Toggle"),
+            ("pause", "set_hotkeys_paused true"),
+            ("resume", "set_hotkeys_paused false"),
+            ("enter-mode", "enter_mode"),
+            ("exit-mode", "exit_mode"),
+            ("about", "show_about"),
+            ("exit", "exit"),
+        ];
+        for (command, call) in cases {
+            let mut handler = Recorder::default();
+            assert_eq!(run(&mut handler, command), Ok(None), "{}", command);
+            assert_eq!(handler.calls, [call], "{}", command);
+        }
+    }
+
+    #[test]
+    fn toggle_pause_flips_the_current_state() {
+        let mut handler = Recorder::default();
+        run(&mut handler, "toggle-pause").unwrap();
+        run(&mut handler, "toggle-pause").unwrap();
+        assert_eq!(handler.calls, ["set_hotkeys_paused true", "set_hotkeys_paused false"]);
+        assert!(!handler.paused);
+    }
+
+    #[test]
+    fn reports_output() {
+        let mut handler = Recorder::default();
+        assert_eq!(run(&mut handler, "mode"), Ok(Some("default".to_string())));
+        run(&mut handler, "enter-mode").unwrap();
+        assert_eq!(run(&mut handler, "mode"), Ok(Some("desktop".to_string())));
+        assert_eq!(run(&mut handler, "hotkeys"), Ok(Some("all registered".to_string())));
+    }
+
+    #[test]
+    fn passes_failures_on() {
+        let mut handler = Recorder { fail_with: Some(DeskError::LastDesktop), ..Recorder::default() };
+        assert_eq!(run(&mut handler, "remove"), Err(DeskError::LastDesktop));
+    }
+
+    #[test]
+    fn commands_survive_a_round_trip_through_text() {
+        let commands = [
+            Command::SwitchDesktop(DesktopTarget::Index(0)),
+            Command::SwitchDesktop(DesktopTarget::Name("mail".to_string())),
+            Command::MoveActiveWindow(DesktopTarget::New),
+            Command::MoveActiveWindowAndFollow(DesktopTarget::Index(9)),
+            Command::CreateDesktop(DesktopTarget::Index(2)),
+            Command::RemoveDesktop,
+            Command::SwapDesktops(DesktopTarget::Index(0), DesktopTarget::Name("chat".to_string())),
+            Command::RenameDesktop("Web dev".to_string()),
+            Command::RenameDesktop(String::new()),
+            Command::ApplyLayout,
+            Command::MoveDesktop(Direction::Right),
+            Command::PinActiveWindow(PinChange::Pin),
+            Command::PinActiveWindow(PinChange::Unpin),
+            Command::PinActiveWindow(PinChange::Toggle),
+            Command::PinActiveApp(PinChange::Pin),
+            Command::PinActiveApp(PinChange::Unpin),
+            Command::PinActiveApp(PinChange::Toggle),
+            Command::ListWindows,
+            Command::FocusWindow("fire inbox".to_string()),
+            Command::FocusOrLaunch("mail".to_string()),
+            Command::ListWindowsElsewhere,
+            Command::BringWindow(0x1a2b),
+            Command::MoveWindow(0x1a2b, DesktopTarget::New),
+            Command::MoveWindowAndFollow(0x1a2b, DesktopTarget::Index(2)),
+            Command::PinWindow(0x1a2b, PinChange::Pin),
+            Command::PinWindow(0x1a2b, PinChange::Unpin),
+            Command::PinWindow(0x1a2b, PinChange::Toggle),
+            Command::PauseHotkeys,
+            Command::ResumeHotkeys,
+            Command::TogglePauseHotkeys,
+            Command::ShowHotkeyStatus,
+            Command::EnterMode,
+            Command::ExitMode,
+            Command::ShowMode,
+            Command::ShowAbout,
+            Command::Exit,
+        ];
+        for command in commands {
+            assert_eq!(command.to_string().parse::<Command>(), Ok(command.clone()), "{}", command);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_commands() {
+        for text in ["", "jump 2", "switch", "switch 0", "swap new 2", "move-desktop up", "focus", "bring 0xzz", "move-window 2", "pin-window"] {
+            assert!(text.parse::<Command>().is_err(), "{:?}", text);
+        }
+        assert_eq!("bring 6699".parse(), Ok(Command::BringWindow(6699)));
+        assert_eq!("switch NEW".parse(), Ok(Command::SwitchDesktop(DesktopTarget::New)));
+    }
+}
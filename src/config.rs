@@ -0,0 +1,238 @@
+// src/config.rs
+
+//! User configuration, read from `%APPDATA%\DeskHopper\config.toml`. Every setting has a
+//! default, so a missing file or a missing section behaves like an empty one.
+
+use anyhow::{Context, Result};
+use log::info;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+use crate::apps::AppSpec;
+use crate::cleanup::Cleanup;
+use crate::command::Command;
+use crate::desktops::{AutoCreate, CreatePolicy};
+use crate::icon::IconStyle;
+use crate::layout::DesktopSpec;
+use crate::notify::NotifyPolicy;
+use crate::rules::WindowRule;
+
+const CONFIG_DIR_NAME: &str = "DeskHopper";
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub tray_icon: TrayIconConfig,
+    pub hotkeys: HotkeysConfig,
+    pub notifications: NotificationsConfig,
+    pub switch: SwitchConfig,
+    pub desktops: DesktopsConfig,
+    /// Where new windows go. The first rule that matches a window applies.
+    pub rules: Vec<WindowRule>,
+    /// Applications for `focus-or-launch`.
+    pub apps: Vec<AppSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DesktopsConfig {
+    /// Whether switching or moving to a desktop that doesn't exist creates it.
+    pub auto_create: AutoCreate,
+    /// Desktops are never created beyond this count. 0 means no limit.
+    pub max_count: usize,
+    /// Whether desktops created on demand are removed once left empty.
+    pub cleanup: Cleanup,
+    /// How long a left desktop has to stay empty before it is removed.
+    pub cleanup_grace_seconds: u64,
+    /// The desktops there should be, in order. Applied at startup and by `apply-layout`.
+    pub layout: Vec<DesktopSpec>,
+}
+
+impl Default for DesktopsConfig {
+    fn default() -> Self {
+        DesktopsConfig {
+            auto_create: AutoCreate::Always,
+            max_count: 20,
+            cleanup: Cleanup::Off,
+            cleanup_grace_seconds: 10,
+            layout: Vec::new(),
+        }
+    }
+}
+
+impl DesktopsConfig {
+    pub fn create_policy(&self) -> CreatePolicy {
+        CreatePolicy { auto_create: self.auto_create, max_count: self.max_count }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SwitchConfig {
+    /// How long to wait for Windows to confirm a desktop switch before focusing a window
+    /// on the new desktop anyway.
+    pub confirm_timeout_ms: u64,
+}
+
+impl Default for SwitchConfig {
+    fn default() -> Self {
+        SwitchConfig { confirm_timeout_ms: 500 }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationsConfig {
+    /// How errors are reported.
+    pub policy: NotifyPolicy,
+    /// How long an identical report stays suppressed after being shown.
+    pub dedup_seconds: u64,
+    /// Upper bound on notifications shown per minute.
+    pub max_per_minute: usize,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        NotificationsConfig { policy: NotifyPolicy::Toast, dedup_seconds: 60, max_per_minute: 5 }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HotkeysConfig {
+    /// Hotkey that pauses and resumes all other hotkeys, e.g. "ctrl+alt+P".
+    /// An empty string disables it.
+    pub pause_toggle: String,
+    /// How long a key must be held to count as a long press.
+    pub long_press_ms: u64,
+    /// How soon a second tap must follow the first to count as a double tap.
+    pub double_tap_ms: u64,
+    /// Hotkey that enters desktop mode, e.g. "ctrl+space". An empty string disables it.
+    pub leader: String,
+    /// How long desktop mode stays active without a key being pressed.
+    pub mode_timeout_ms: u64,
+    /// Additional bindings. One with the same keys as a built-in binding replaces it.
+    pub bindings: Vec<BindingConfig>,
+}
+
+impl Default for HotkeysConfig {
+    fn default() -> Self {
+        HotkeysConfig {
+            pause_toggle: "ctrl+alt+P".to_string(),
+            long_press_ms: 500,
+            double_tap_ms: 300,
+            // Off by default: the obvious choices clash with input methods and editors.
+            leader: String::new(),
+            mode_timeout_ms: 5000,
+            bindings: Vec::new(),
+        }
+    }
+}
+
+/// A hotkey with a command for each gesture, written as on the command line, e.g.
+/// `{ keys = "ctrl+3", tap = "switch 3", double_tap = "move-follow 3" }`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BindingConfig {
+    pub keys: String,
+    pub tap: Option<CommandSpec>,
+    pub long_press: Option<CommandSpec>,
+    pub double_tap: Option<CommandSpec>,
+}
+
+/// A command in its textual form, as accepted by `deskhopper <command>`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct CommandSpec(pub Command);
+
+impl TryFrom<String> for CommandSpec {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        value.parse().map(CommandSpec)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrayIconConfig {
+    /// What to draw on the tray icon.
+    pub label: TrayIconLabel,
+    pub background: Color,
+    pub foreground: Color,
+}
+
+impl Default for TrayIconConfig {
+    fn default() -> Self {
+        TrayIconConfig {
+            label: TrayIconLabel::Number,
+            background: Color([0x1F, 0x5F, 0xA8, 0xFF]),
+            foreground: Color([0xFF, 0xFF, 0xFF, 0xFF]),
+        }
+    }
+}
+
+impl TrayIconConfig {
+    pub fn style(&self) -> IconStyle {
+        IconStyle {
+            background: image::Rgba(self.background.0),
+            foreground: image::Rgba(self.foreground.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrayIconLabel {
+    /// The 1-based number of the current desktop.
+    Number,
+    /// The first letters of the current desktop's name, or its number if it has none.
+    Name,
+    /// The bundled static icon.
+    None,
+}
+
+/// An RGBA colour, written in the config as `"#RRGGBB"` or `"#RRGGBBAA"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Color(pub [u8; 4]);
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        let hex = value.strip_prefix('#').unwrap_or(&value);
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid colour '{}', expected #RRGGBB or #RRGGBBAA", value));
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+        let alpha = if hex.len() == 8 { channel(3) } else { 0xFF };
+        Ok(Color([channel(0), channel(1), channel(2), alpha]))
+    }
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    let app_data = std::env::var_os("APPDATA")?;
+    Some(PathBuf::from(app_data).join(CONFIG_DIR_NAME).join(CONFIG_FILE_NAME))
+}
+
+impl Config {
+    /// Loads the config file, falling back to defaults if it doesn't exist.
+    pub fn load() -> Result<Config> {
+        let Some(path) = config_path() else {
+            info!("APPDATA is not set; using default configuration.");
+            return Ok(Config::default());
+        };
+        if !path.exists() {
+            info!("No config file at {}; using default configuration.", path.display());
+            return Ok(Config::default());
+        }
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let config = toml::from_str(&text)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+        info!("Loaded configuration from {}", path.display());
+        Ok(config)
+    }
+}
@@ -0,0 +1,194 @@
+// src/desktops.rs
+
+//! When a switch or move targets a desktop past the last one, the missing desktops are
+//! created only as far as the configured policy and desktop limit allow. `plan` needs
+//! nothing but the target and the desktop count to tell whether the target exists, may be
+//! created right away, needs the user's go-ahead or is out of bounds.
+//!
+//! Windows offers no way to reorder desktops, so inserting, swapping and moving desktops
+//! is done by moving windows and names between them; `rearrange` works out where each
+//! desktop's contents go.
+
+use serde::Deserialize;
+
+use crate::command::Direction;
+use crate::error::DeskError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoCreate {
+    /// Create every desktop up to the target.
+    Always,
+    /// Create the target only if it directly follows the last desktop.
+    Next,
+    /// Ask before creating any desktop.
+    Ask,
+    /// Never create desktops.
+    Refuse,
+}
+
+/// What it takes for a target desktop to exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CreatePlan {
+    Exists,
+    /// Create this many desktops.
+    Create(usize),
+    /// Create this many desktops if the user agrees.
+    Confirm(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreatePolicy {
+    pub auto_create: AutoCreate,
+    /// Desktops are never created beyond this count. 0 means no limit.
+    pub max_count: usize,
+}
+
+impl CreatePolicy {
+    /// Decides how the 0-based `desktop_idx` comes to exist when there are `count` desktops.
+    pub fn plan(&self, desktop_idx: usize, count: usize) -> Result<CreatePlan, DeskError> {
+        if desktop_idx < count {
+            return Ok(CreatePlan::Exists);
+        }
+        if self.max_count > 0 && desktop_idx >= self.max_count {
+            return Err(DeskError::DesktopLimit(self.max_count));
+        }
+        let missing = desktop_idx + 1 - count;
+        match self.auto_create {
+            AutoCreate::Always => Ok(CreatePlan::Create(missing)),
+            AutoCreate::Next if missing == 1 => Ok(CreatePlan::Create(1)),
+            AutoCreate::Ask => Ok(CreatePlan::Confirm(missing)),
+            AutoCreate::Next | AutoCreate::Refuse => Err(DeskError::DesktopNotFound(desktop_idx)),
+        }
+    }
+}
+
+/// A change to the order of desktops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rearrangement {
+    /// A new, empty desktop at this 0-based position. The caller appends one first, so
+    /// the contents from the position on can shift right onto it.
+    InsertAt(usize),
+    Swap(usize, usize),
+    /// Moves the desktop at this index one place in the direction.
+    Shift(usize, Direction),
+}
+
+/// Where the contents of each of the `count` desktops go: the desktop at index `i` ends up
+/// at `result[i]`. For `InsertAt`, `count` is the number of desktops before appending.
+pub fn rearrange(rearrangement: Rearrangement, count: usize) -> Result<Vec<usize>, DeskError> {
+    let mut destinations: Vec<usize> = (0..count).collect();
+    match rearrangement {
+        Rearrangement::InsertAt(position) => {
+            if position > count {
+                return Err(DeskError::DesktopNotFound(position));
+            }
+            for destination in &mut destinations[position..] {
+                *destination += 1;
+            }
+        }
+        Rearrangement::Swap(a, b) => {
+            for idx in [a, b] {
+                if idx >= count {
+                    return Err(DeskError::DesktopNotFound(idx));
+                }
+            }
+            destinations.swap(a, b);
+        }
+        Rearrangement::Shift(idx, direction) => {
+            if idx >= count {
+                return Err(DeskError::DesktopNotFound(idx));
+            }
+            // At either end there's nowhere to go; that's not worth an error.
+            let neighbour = match direction {
+                Direction::Left => idx.checked_sub(1),
+                Direction::Right => Some(idx + 1).filter(|&next| next < count),
+            };
+            if let Some(neighbour) = neighbour {
+                destinations.swap(idx, neighbour);
+            }
+        }
+    }
+    Ok(destinations)
+}
+
+/// The index of the desktop called `name`, ignoring case. `names` lists every desktop's
+/// name in order, empty for unnamed ones.
+pub fn find_by_name(names: &[String], name: &str) -> Option<usize> {
+    let wanted = name.to_lowercase();
+    names.iter().position(|candidate| !candidate.is_empty() && candidate.to_lowercase() == wanted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(auto_create: AutoCreate, max_count: usize) -> CreatePolicy {
+        CreatePolicy { auto_create, max_count }
+    }
+
+    #[test]
+    fn existing_desktops_need_nothing() {
+        for auto_create in [AutoCreate::Always, AutoCreate::Next, AutoCreate::Ask, AutoCreate::Refuse] {
+            assert_eq!(policy(auto_create, 2).plan(2, 3), Ok(CreatePlan::Exists), "{:?}", auto_create);
+        }
+    }
+
+    #[test]
+    fn plans_missing_desktops_by_policy() {
+        let cases = [
+            (AutoCreate::Always, 3, Ok(CreatePlan::Create(1))),
+            (AutoCreate::Always, 5, Ok(CreatePlan::Create(3))),
+            (AutoCreate::Next, 3, Ok(CreatePlan::Create(1))),
+            (AutoCreate::Next, 4, Err(DeskError::DesktopNotFound(4))),
+            (AutoCreate::Ask, 3, Ok(CreatePlan::Confirm(1))),
+            (AutoCreate::Ask, 5, Ok(CreatePlan::Confirm(3))),
+            (AutoCreate::Refuse, 3, Err(DeskError::DesktopNotFound(3))),
+        ];
+        for (auto_create, desktop_idx, expected) in cases {
+            assert_eq!(policy(auto_create, 0).plan(desktop_idx, 3), expected, "{:?} {}", auto_create, desktop_idx);
+        }
+    }
+
+    #[test]
+    fn creates_nothing_past_the_limit() {
+        let always = policy(AutoCreate::Always, 5);
+        assert_eq!(always.plan(4, 3), Ok(CreatePlan::Create(2)));
+        assert_eq!(always.plan(5, 3), Err(DeskError::DesktopLimit(5)));
+        assert_eq!(policy(AutoCreate::Ask, 4).plan(4, 4), Err(DeskError::DesktopLimit(4)));
+        // Desktops beyond the limit that exist already are still there to use.
+        assert_eq!(policy(AutoCreate::Refuse, 2).plan(3, 4), Ok(CreatePlan::Exists));
+    }
+
+    #[test]
+    fn rearranges_desktops() {
+        let cases = [
+            (Rearrangement::InsertAt(0), vec![1, 2, 3]),
+            (Rearrangement::InsertAt(1), vec![0, 2, 3]),
+            (Rearrangement::InsertAt(3), vec![0, 1, 2]),
+            (Rearrangement::Swap(0, 2), vec![2, 1, 0]),
+            (Rearrangement::Swap(1, 1), vec![0, 1, 2]),
+            (Rearrangement::Shift(1, Direction::Left), vec![1, 0, 2]),
+            (Rearrangement::Shift(1, Direction::Right), vec![0, 2, 1]),
+            // Nowhere to go at either end.
+            (Rearrangement::Shift(0, Direction::Left), vec![0, 1, 2]),
+            (Rearrangement::Shift(2, Direction::Right), vec![0, 1, 2]),
+        ];
+        for (rearrangement, expected) in cases {
+            assert_eq!(rearrange(rearrangement, 3), Ok(expected), "{:?}", rearrangement);
+        }
+    }
+
+    #[test]
+    fn rejects_desktops_that_do_not_exist() {
+        let cases = [
+            (Rearrangement::InsertAt(4), 4),
+            (Rearrangement::Swap(0, 3), 3),
+            (Rearrangement::Swap(5, 0), 5),
+            (Rearrangement::Shift(3, Direction::Left), 3),
+        ];
+        for (rearrangement, missing) in cases {
+            assert_eq!(rearrange(rearrangement, 3), Err(DeskError::DesktopNotFound(missing)), "{:?}", rearrangement);
+        }
+    }
+}
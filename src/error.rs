@@ -0,0 +1,190 @@
+// src/error.rs
+
+//! The ways a DeskHopper action can fail. Callers match on the variant rather than on
+//! message text: notifications pick their title from it, IPC replies carry `kind()` so
+//! scripts can tell failures apart, and `is_retryable()` separates transient failures of
+//! the virtual desktop service from ones that will fail the same way again.
+
+use std::fmt;
+
+/// Why a window can't be moved to another desktop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotMovableReason {
+    /// The window belongs to an elevated process and DeskHopper isn't elevated.
+    Elevated,
+    /// A shell window such as the taskbar or the desktop background.
+    System,
+    /// Hidden by the window manager, e.g. a suspended UWP app.
+    Cloaked,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeskError {
+    /// A 0-based desktop index that doesn't exist and wasn't created.
+    DesktopNotFound(usize),
+    /// No desktop has this name.
+    UnknownDesktopName(String),
+    CreateDesktopFailed(String),
+    /// The target is beyond the configured maximum number of desktops.
+    DesktopLimit(usize),
+    /// The user declined creating the 0-based desktop index when asked.
+    CreationDeclined(usize),
+    /// Windows always keeps at least one desktop.
+    LastDesktop,
+    /// There is no application window to act on.
+    NoActiveWindow,
+    /// No window has this handle, or matches this query.
+    WindowNotFound(String),
+    /// No app with this name is defined in the config file.
+    UnknownApp(String),
+    /// An app's command couldn't be started.
+    LaunchFailed(String),
+    WindowNotMovable(Option<NotMovableReason>),
+    /// The virtual desktop service can't be reached, e.g. while Explorer restarts.
+    BackendUnavailable(String),
+    /// Another application already registered this key combination.
+    HotkeyTaken(String),
+    InvalidHotkey(String),
+    /// The config file couldn't be read or parsed.
+    Config(String),
+    /// Any other failure reported by the virtual desktop API.
+    Backend(String),
+}
+
+impl DeskError {
+    /// A stable, machine-readable name for the kind of failure, used in IPC replies.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DeskError::DesktopNotFound(_) | DeskError::UnknownDesktopName(_) => "desktop-not-found",
+            DeskError::CreateDesktopFailed(_) => "create-desktop-failed",
+            DeskError::DesktopLimit(_) => "desktop-limit",
+            DeskError::CreationDeclined(_) => "creation-declined",
+            DeskError::LastDesktop => "last-desktop",
+            DeskError::NoActiveWindow => "no-active-window",
+            DeskError::WindowNotFound(_) => "window-not-found",
+            DeskError::UnknownApp(_) => "unknown-app",
+            DeskError::LaunchFailed(_) => "launch-failed",
+            DeskError::WindowNotMovable(_) => "window-not-movable",
+            DeskError::BackendUnavailable(_) => "backend-unavailable",
+            DeskError::HotkeyTaken(_) => "hotkey-taken",
+            DeskError::InvalidHotkey(_) => "invalid-hotkey",
+            DeskError::Config(_) => "config-error",
+            DeskError::Backend(_) => "backend-error",
+        }
+    }
+
+    /// A short heading for notifications.
+    pub fn title(&self) -> &'static str {
+        match self {
+            DeskError::DesktopNotFound(_) | DeskError::UnknownDesktopName(_) => "Desktop Not Found",
+            DeskError::CreateDesktopFailed(_) => "Desktop Creation Error",
+            DeskError::DesktopLimit(_) => "Desktop Limit Reached",
+            DeskError::CreationDeclined(_) => "Desktop Not Created",
+            DeskError::LastDesktop => "Desktop Not Removed",
+            DeskError::NoActiveWindow | DeskError::WindowNotMovable(_) => "Move Window Error",
+            DeskError::WindowNotFound(_) => "Window Not Found",
+            DeskError::UnknownApp(_) | DeskError::LaunchFailed(_) => "Launch Error",
+            DeskError::BackendUnavailable(_) => "Virtual Desktops Unavailable",
+            DeskError::HotkeyTaken(_) | DeskError::InvalidHotkey(_) => "Hotkey Registration Error",
+            DeskError::Config(_) => "Configuration Error",
+            DeskError::Backend(_) => "Virtual Desktop Error",
+        }
+    }
+
+    /// Like `From<winvd::Error>`, but names the desktop when the error is that it doesn't exist.
+    pub fn for_desktop(desktop_idx: usize, e: winvd::Error) -> Self {
+        match e {
+            winvd::Error::DesktopNotFound => DeskError::DesktopNotFound(desktop_idx),
+            other => other.into(),
+        }
+    }
+
+    /// Like `From<winvd::Error>`, but names the window when the error is that it doesn't exist.
+    pub fn for_window(hwnd: isize, e: winvd::Error) -> Self {
+        match e {
+            winvd::Error::WindowNotFound => DeskError::WindowNotFound(format!("{:#x}", hwnd)),
+            other => other.into(),
+        }
+    }
+
+    /// Classifies a hotkey registration failure for `binding`, as written by the user.
+    pub fn from_hotkey_error(binding: &str, e: global_hotkey::Error) -> Self {
+        match e {
+            global_hotkey::Error::AlreadyRegistered(_) => DeskError::HotkeyTaken(binding.to_string()),
+            other => DeskError::InvalidHotkey(format!("{}: {}", binding, other)),
+        }
+    }
+
+    /// Whether the same action may succeed if tried again later.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, DeskError::BackendUnavailable(_))
+    }
+}
+
+impl fmt::Display for DeskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeskError::DesktopNotFound(idx) => write!(f, "desktop {} does not exist", idx + 1),
+            DeskError::UnknownDesktopName(name) => write!(f, "there is no desktop named '{}'", name),
+            DeskError::CreateDesktopFailed(detail) => write!(f, "failed to create a virtual desktop: {}", detail),
+            DeskError::DesktopLimit(max) => write!(f, "no more than {} desktops may be created", max),
+            DeskError::CreationDeclined(idx) => write!(f, "creating desktop {} was declined", idx + 1),
+            DeskError::LastDesktop => write!(f, "the only desktop can't be removed"),
+            DeskError::NoActiveWindow => write!(f, "there is no active window"),
+            DeskError::WindowNotFound(query) if query.is_empty() => write!(f, "the window no longer exists"),
+            DeskError::WindowNotFound(query) => write!(f, "no window matches '{}'", query),
+            DeskError::UnknownApp(name) => write!(f, "no app named '{}' is defined in the config file", name),
+            DeskError::LaunchFailed(detail) => write!(f, "failed to start the app: {}", detail),
+            DeskError::WindowNotMovable(reason) => match reason {
+                Some(NotMovableReason::Elevated) => {
+                    write!(f, "the window belongs to an elevated application; run DeskHopper as administrator to move it")
+                }
+                Some(NotMovableReason::System) => write!(f, "system windows can't be moved between desktops"),
+                Some(NotMovableReason::Cloaked) => write!(f, "the window is hidden and can't be moved"),
+                None => write!(f, "the window can't be moved"),
+            },
+            DeskError::BackendUnavailable(detail) => write!(f, "the virtual desktop service is unavailable: {}", detail),
+            DeskError::HotkeyTaken(binding) => write!(f, "{} is already in use by another application", binding),
+            DeskError::InvalidHotkey(detail) => write!(f, "invalid hotkey: {}", detail),
+            DeskError::Config(detail) => write!(f, "invalid configuration: {}", detail),
+            DeskError::Backend(detail) => write!(f, "virtual desktop operation failed: {}", detail),
+        }
+    }
+}
+
+impl std::error::Error for DeskError {}
+
+impl From<winvd::Error> for DeskError {
+    fn from(e: winvd::Error) -> Self {
+        match e {
+            // The COM server behind virtual desktops is gone or not up yet, typically
+            // because Explorer crashed or is restarting.
+            winvd::Error::ClassNotRegistered
+            | winvd::Error::RpcServerNotAvailable
+            | winvd::Error::ComObjectNotConnected
+            | winvd::Error::ComNotInitialized => DeskError::BackendUnavailable(format!("{:?}", e)),
+            winvd::Error::CreateDesktopFailed => DeskError::CreateDesktopFailed(format!("{:?}", e)),
+            winvd::Error::WindowNotFound => DeskError::WindowNotFound(String::new()),
+            other => DeskError::Backend(format!("{:?}", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_missing_window_is_not_reported_as_no_active_window() {
+        assert_eq!(DeskError::from(winvd::Error::WindowNotFound), DeskError::WindowNotFound(String::new()));
+        assert_eq!(DeskError::from(winvd::Error::WindowNotFound).to_string(), "the window no longer exists");
+        assert_eq!(
+            DeskError::for_window(0x1234, winvd::Error::WindowNotFound),
+            DeskError::WindowNotFound("0x1234".to_string())
+        );
+        assert_eq!(
+            DeskError::for_window(0x1234, winvd::Error::ClassNotRegistered),
+            DeskError::BackendUnavailable("ClassNotRegistered".to_string())
+        );
+    }
+}
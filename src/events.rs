@@ -0,0 +1,94 @@
+// src/events.rs
+
+//! Delivery of events from other threads to the event loop. Callbacks and worker threads
+//! send through an `EventSink`, which is the event loop's proxy in DeskHopper and a plain
+//! channel in tests, so the order and completeness of delivery can be checked without a
+//! window system.
+
+use log::error;
+use std::{sync::mpsc, thread, time::Duration};
+use tao::event_loop::EventLoopProxy;
+
+/// Something that takes events for the event loop.
+pub trait EventSink<E> {
+    /// Sends `event`. Returns false once nothing receives events any more.
+    fn send(&self, event: E) -> bool;
+}
+
+// Lets a proxy for the application's event type take any event that converts into it.
+impl<T: 'static, E: Into<T>> EventSink<E> for EventLoopProxy<T> {
+    fn send(&self, event: E) -> bool {
+        self.send_event(event.into()).is_ok()
+    }
+}
+
+impl<E> EventSink<E> for mpsc::Sender<E> {
+    fn send(&self, event: E) -> bool {
+        mpsc::Sender::send(self, event).is_ok()
+    }
+}
+
+/// An event handler that passes every event it's called with to `sink` right away,
+/// wrapped by `wrap`. `what` names the events in the log.
+pub fn forwarder<E, T, S, W>(sink: S, wrap: W, what: &'static str) -> impl Fn(E) + Send + Sync + 'static
+where
+    S: EventSink<T> + Send + Sync + 'static,
+    W: Fn(E) -> T + Send + Sync + 'static,
+{
+    move |event| {
+        if !sink.send(wrap(event)) {
+            error!("Failed to send {} event to main loop. Main loop likely exited.", what);
+        }
+    }
+}
+
+/// Sends `event` to `sink` once `delay` has passed, without blocking the caller.
+pub fn send_after<E, S>(sink: &S, delay: Duration, event: E)
+where
+    E: Send + 'static,
+    S: EventSink<E> + Clone + Send + 'static,
+{
+    let sink = sink.clone();
+    thread::spawn(move || {
+        thread::sleep(delay);
+        if !sink.send(event) {
+            error!("Failed to send timer event to main loop. Main loop likely exited.");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn forwards_bursts_completely_and_in_order() {
+        const BURST: usize = 10_000;
+        let (sender, receiver) = mpsc::channel();
+        let forward = forwarder(sender, |n: usize| n * 2, "test");
+        // Fired from another thread while this one receives, as tray callbacks are.
+        let burst = thread::spawn(move || (0..BURST).for_each(forward));
+        let received: Vec<usize> = receiver.iter().collect();
+        burst.join().unwrap();
+        assert_eq!(received, (0..BURST).map(|n| n * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn keeps_going_after_the_receiver_is_gone() {
+        let (sender, receiver) = mpsc::channel();
+        drop(receiver);
+        let forward = forwarder(sender, |n: u32| n, "test");
+        forward(1);
+        forward(2);
+    }
+
+    #[test]
+    fn sends_after_the_delay() {
+        let (sender, receiver) = mpsc::channel();
+        let start = Instant::now();
+        send_after(&sender, Duration::from_millis(50), "due");
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok("due"));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,324 @@
+// src/executor.rs
+
+//! Runs desktop actions on a dedicated worker thread, so slow virtual desktop calls never
+//! hold up the event loop. Actions queue up while one is running; before running the next
+//! ones the worker drops those that later actions make pointless (see `coalesce`). The
+//! first action of a burst runs as soon as it arrives, so a quick Ctrl+2, Ctrl+5, Ctrl+7
+//! switches to desktop 2, then straight on to 7 without visiting 5 or restoring focus on
+//! the desktops passed through. Outcomes go back to the event loop as events.
+
+use log::{debug, error, info};
+use std::{sync::mpsc, thread};
+
+use crate::apps::AppSpec;
+use crate::command::{DesktopTarget, Direction, PinChange};
+use crate::error::DeskError;
+use crate::events::EventSink;
+use crate::layout::DesktopSpec;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    SwitchDesktop(DesktopTarget),
+    /// Moves the window with this handle, captured when the command was given.
+    MoveWindow(DesktopTarget, isize),
+    /// Moves the window, then switches to the desktop it was moved to.
+    MoveWindowAndFollow(DesktopTarget, isize),
+    /// Focuses the remembered (or some other) window on the current desktop after a switch.
+    FocusCurrentDesktop,
+    /// Removes the desktops created on demand that were left empty.
+    CollectDesktops,
+    CreateDesktopAt(DesktopTarget),
+    RemoveCurrentDesktop,
+    SwapDesktops(DesktopTarget, DesktopTarget),
+    MoveCurrentDesktop(Direction),
+    RenameCurrentDesktop(String),
+    /// Creates, names and orders desktops to match the declared layout.
+    ApplyLayout(Vec<DesktopSpec>),
+    /// Pins or unpins the window with this handle, or its whole application if `whole_app`.
+    PinWindow { hwnd: isize, change: PinChange, whole_app: bool },
+    /// Lists the windows on every desktop.
+    ListWindows,
+    /// Lists the windows on desktops other than the current one.
+    ListWindowsElsewhere,
+    /// Focuses the window that best matches the query, switching to its desktop.
+    FocusWindow(String),
+    /// Focuses a window of the app, switching to its desktop, or starts the app.
+    FocusOrLaunch(AppSpec),
+    /// Moves the window with this handle to the current desktop and focuses it.
+    BringWindow(isize),
+}
+
+impl Action {
+    /// Whether the action changes which desktop has which index.
+    fn rearranges_desktops(&self) -> bool {
+        matches!(
+            self,
+            Action::CreateDesktopAt(_)
+                | Action::RemoveCurrentDesktop
+                | Action::SwapDesktops(..)
+                | Action::MoveCurrentDesktop(_)
+                | Action::ApplyLayout(_)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActionOutcome {
+    Done,
+    /// Done, with something to report to the user.
+    Output(String),
+    /// Switched to the 0-based `desktop_idx`; `was_current` if it already was the current one.
+    Switched { desktop_idx: usize, was_current: bool },
+    /// Skipped because a later action made it pointless.
+    Superseded,
+}
+
+/// A finished action, with the channel its IPC reply goes to if it came over IPC.
+#[derive(Debug, Clone)]
+pub struct ActionFinished {
+    pub action: Action,
+    pub result: Result<ActionOutcome, DeskError>,
+    pub reply_to: Option<mpsc::Sender<String>>,
+}
+
+/// Carries out actions on the worker thread. In DeskHopper this talks to the virtual
+/// desktop service; tests put a fake in its place.
+pub trait DesktopBackend {
+    fn run(&mut self, action: Action) -> Result<ActionOutcome, DeskError>;
+}
+
+struct Job {
+    action: Action,
+    reply_to: Option<mpsc::Sender<String>>,
+}
+
+pub struct Executor {
+    sender: mpsc::Sender<Job>,
+}
+
+impl Executor {
+    /// Starts the worker thread, which carries out each action with the backend built by
+    /// `make_backend` and sends the outcome to `sink`. The backend is built on the worker
+    /// thread, so it may hold state that can't leave it, such as window handles.
+    pub fn spawn<S, M, B>(sink: S, make_backend: M) -> Self
+    where
+        S: EventSink<ActionFinished> + Send + 'static,
+        M: FnOnce() -> B + Send + 'static,
+        B: DesktopBackend,
+    {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            info!("Action worker thread started.");
+            let mut backend = make_backend();
+            while let Ok(first) = receiver.recv() {
+                let mut jobs = vec![first];
+                jobs.extend(receiver.try_iter());
+
+                let actions: Vec<Action> = jobs.iter().map(|job| job.action.clone()).collect();
+                let keep = coalesce(&actions);
+                for (job, keep) in jobs.into_iter().zip(keep) {
+                    let result = if keep {
+                        backend.run(job.action.clone())
+                    } else {
+                        debug!("Skipping {:?}, superseded by a later action.", job.action);
+                        Ok(ActionOutcome::Superseded)
+                    };
+                    let finished = ActionFinished { action: job.action, result, reply_to: job.reply_to };
+                    if !sink.send(finished) {
+                        error!("Failed to send action result to main loop. Main loop likely exited.");
+                        return;
+                    }
+                }
+            }
+            info!("Action worker thread finished.");
+        });
+        Executor { sender }
+    }
+
+    /// Queues an action. If `reply_to` is given, the outcome is sent there once it's done.
+    pub fn submit(&self, action: Action, reply_to: Option<mpsc::Sender<String>>) {
+        if let Err(mpsc::SendError(job)) = self.sender.send(Job { action, reply_to }) {
+            error!("Action worker has stopped; dropping {:?}.", job.action);
+        }
+    }
+}
+
+/// Decides which of a batch of queued actions, oldest first, still need to run:
+/// - a switch is pointless if another switch follows with no move, change to the order of
+///   desktops or action on the current desktop in between, so those keep happening on the
+///   desktop they were given on;
+/// - a focus restore is pointless if a switch or another focus restore follows;
+/// - moving a window is pointless if a later move of the same window follows, and bringing
+///   a window here is itself a move to whatever desktop is current by then;
+/// - removing empty desktops is pointless if another removal follows.
+///
+/// A move that follows the window counts as both a move and a switch, and is never
+/// dropped itself. So does focusing a window by query or app, which may switch to its desktop.
+pub fn coalesce(actions: &[Action]) -> Vec<bool> {
+    actions
+        .iter()
+        .enumerate()
+        .map(|(i, action)| !is_superseded(action, &actions[i + 1..]))
+        .collect()
+}
+
+fn is_superseded(action: &Action, later: &[Action]) -> bool {
+    for next in later {
+        match (action, next) {
+            (Action::SwitchDesktop(_), Action::SwitchDesktop(_)) => return true,
+            (
+                Action::SwitchDesktop(_),
+                Action::MoveWindow(..)
+                | Action::MoveWindowAndFollow(..)
+                | Action::BringWindow(_)
+                | Action::FocusWindow(_)
+                | Action::FocusOrLaunch(_)
+                | Action::RenameCurrentDesktop(_)
+                | Action::ListWindowsElsewhere,
+            ) => return false,
+            (Action::SwitchDesktop(_), next) if next.rearranges_desktops() => return false,
+            (
+                Action::FocusCurrentDesktop,
+                Action::SwitchDesktop(_)
+                | Action::MoveWindowAndFollow(..)
+                | Action::FocusCurrentDesktop
+                | Action::FocusWindow(_)
+                | Action::FocusOrLaunch(_),
+            ) => return true,
+            (Action::CollectDesktops, Action::CollectDesktops) => return true,
+            (
+                Action::MoveWindow(_, hwnd) | Action::BringWindow(hwnd),
+                Action::MoveWindow(_, next_hwnd) | Action::MoveWindowAndFollow(_, next_hwnd) | Action::BringWindow(next_hwnd),
+            ) if hwnd == next_hwnd =>
+            {
+                return true
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn switch(number: usize) -> Action {
+        Action::SwitchDesktop(DesktopTarget::Index(number - 1))
+    }
+
+    #[test]
+    fn coalescing() {
+        let cases = [
+            (vec![switch(1), switch(4), switch(6)], vec![false, false, true]),
+            // The window is moved off the desktop it was on when the move was given.
+            (vec![switch(1), Action::MoveWindow(DesktopTarget::Index(2), 7), switch(6)], vec![true, true, true]),
+            (vec![switch(1), Action::MoveWindowAndFollow(DesktopTarget::New, 7)], vec![true, true]),
+            (vec![switch(1), Action::BringWindow(7), switch(6)], vec![true, true, true]),
+            (vec![switch(1), Action::FocusWindow("mail".to_string())], vec![true, true]),
+            (vec![switch(2), Action::RemoveCurrentDesktop, switch(2)], vec![true, true, true]),
+            (vec![switch(2), Action::RenameCurrentDesktop("mail".to_string()), switch(3)], vec![true, true, true]),
+            (vec![switch(2), Action::ListWindowsElsewhere, switch(3)], vec![true, true, true]),
+            (vec![switch(2), Action::ListWindows, switch(3)], vec![false, true, true]),
+            (vec![switch(2), Action::CollectDesktops, switch(3)], vec![false, true, true]),
+            (
+                vec![switch(2), Action::FocusCurrentDesktop, switch(5), Action::FocusCurrentDesktop],
+                vec![false, false, true, true],
+            ),
+            (vec![Action::FocusCurrentDesktop, Action::FocusWindow("mail".to_string())], vec![false, true]),
+            (vec![Action::CollectDesktops, switch(2), Action::CollectDesktops], vec![false, true, true]),
+            (
+                vec![
+                    Action::MoveWindow(DesktopTarget::Index(1), 7),
+                    Action::MoveWindow(DesktopTarget::Index(1), 8),
+                    Action::MoveWindow(DesktopTarget::Index(2), 7),
+                ],
+                vec![false, true, true],
+            ),
+            (vec![Action::BringWindow(7), Action::MoveWindowAndFollow(DesktopTarget::Index(3), 7)], vec![false, true]),
+            (vec![Action::MoveWindowAndFollow(DesktopTarget::Index(3), 7), switch(1)], vec![true, true]),
+        ];
+        for (actions, expected) in cases {
+            assert_eq!(coalesce(&actions), expected, "{:?}", actions);
+        }
+    }
+
+    // Reports each action it's given, then waits for the test to let it finish.
+    struct FakeBackend {
+        started: mpsc::Sender<Action>,
+        release: mpsc::Receiver<()>,
+    }
+
+    impl DesktopBackend for FakeBackend {
+        fn run(&mut self, action: Action) -> Result<ActionOutcome, DeskError> {
+            self.started.send(action.clone()).unwrap();
+            self.release.recv().unwrap();
+            match action {
+                Action::SwitchDesktop(DesktopTarget::Index(desktop_idx)) => {
+                    Ok(ActionOutcome::Switched { desktop_idx, was_current: false })
+                }
+                Action::RemoveCurrentDesktop => Err(DeskError::LastDesktop),
+                _ => Ok(ActionOutcome::Done),
+            }
+        }
+    }
+
+    struct Worker {
+        executor: Executor,
+        started: mpsc::Receiver<Action>,
+        release: mpsc::Sender<()>,
+        finished: mpsc::Receiver<ActionFinished>,
+    }
+
+    fn start_worker() -> Worker {
+        let (sink, finished) = mpsc::channel();
+        let (started_tx, started) = mpsc::channel();
+        let (release, release_rx) = mpsc::channel();
+        let executor = Executor::spawn(sink, move || FakeBackend { started: started_tx, release: release_rx });
+        Worker { executor, started, release, finished }
+    }
+
+    #[test]
+    fn worker_skips_actions_queued_behind_a_running_one() {
+        let worker = start_worker();
+        worker.executor.submit(switch(2), None);
+        // Wait until the worker is busy, so the rest queue up behind it.
+        assert_eq!(worker.started.recv().unwrap(), switch(2));
+        let burst = [Action::FocusCurrentDesktop, switch(5), Action::FocusCurrentDesktop, switch(7), Action::FocusCurrentDesktop];
+        for action in burst {
+            worker.executor.submit(action, None);
+        }
+        for _ in 0..3 {
+            worker.release.send(()).unwrap();
+        }
+
+        let finished: Vec<(Action, Result<ActionOutcome, DeskError>)> =
+            worker.finished.iter().take(6).map(|finished| (finished.action, finished.result)).collect();
+        assert_eq!(
+            finished,
+            [
+                (switch(2), Ok(ActionOutcome::Switched { desktop_idx: 1, was_current: false })),
+                (Action::FocusCurrentDesktop, Ok(ActionOutcome::Superseded)),
+                (switch(5), Ok(ActionOutcome::Superseded)),
+                (Action::FocusCurrentDesktop, Ok(ActionOutcome::Superseded)),
+                (switch(7), Ok(ActionOutcome::Switched { desktop_idx: 6, was_current: false })),
+                (Action::FocusCurrentDesktop, Ok(ActionOutcome::Done)),
+            ]
+        );
+        let ran: Vec<Action> = worker.started.try_iter().collect();
+        assert_eq!(ran, [switch(7), Action::FocusCurrentDesktop]);
+    }
+
+    #[test]
+    fn worker_hands_outcomes_to_the_reply_channel_they_came_with() {
+        let worker = start_worker();
+        let (reply_to, _replies) = mpsc::channel();
+        worker.executor.submit(Action::RemoveCurrentDesktop, Some(reply_to));
+        worker.release.send(()).unwrap();
+
+        let finished = worker.finished.recv().unwrap();
+        assert_eq!(finished.action, Action::RemoveCurrentDesktop);
+        assert_eq!(finished.result, Err(DeskError::LastDesktop));
+        assert!(finished.reply_to.is_some());
+    }
+}
@@ -0,0 +1,56 @@
+// src/foreground.rs
+
+//! Keeps track of the last application window that had the foreground. Opening the
+//! tray menu hands the foreground to the taskbar, so by the time a menu item is
+//! clicked `GetForegroundWindow` no longer returns the window the user meant.
+
+use anyhow::Result;
+use log::debug;
+use std::sync::atomic::{AtomicIsize, Ordering};
+use windows::Win32::{
+    Foundation::HWND,
+    UI::{
+        Accessibility::HWINEVENTHOOK,
+        WindowsAndMessaging::{GetForegroundWindow, EVENT_SYSTEM_FOREGROUND},
+    },
+};
+
+use crate::window;
+
+// HWND stored as an integer so it can live in a static; 0 means none.
+static LAST_FOREGROUND: AtomicIsize = AtomicIsize::new(0);
+
+/// Installs the foreground change hook. Must be called on a thread that pumps
+/// messages (the event loop thread), and only once.
+pub fn start_tracking() -> Result<()> {
+    remember(unsafe { GetForegroundWindow() });
+    window::install_event_hook(EVENT_SYSTEM_FOREGROUND, Some(foreground_changed))
+}
+
+/// The most recent foreground window that wasn't part of the taskbar or DeskHopper itself.
+pub fn last_foreground_window() -> Option<HWND> {
+    match LAST_FOREGROUND.load(Ordering::Relaxed) {
+        0 => None,
+        raw => Some(HWND(raw as *mut _)),
+    }
+}
+
+fn remember(hwnd: HWND) {
+    if hwnd.is_invalid() || window::is_taskbar_window(hwnd) {
+        return;
+    }
+    LAST_FOREGROUND.store(hwnd.0 as isize, Ordering::Relaxed);
+}
+
+extern "system" fn foreground_changed(
+    _hook: HWINEVENTHOOK,
+    _event: u32,
+    hwnd: HWND,
+    _id_object: i32,
+    _id_child: i32,
+    _event_thread: u32,
+    _event_time: u32,
+) {
+    debug!("Foreground window changed to {:?}", hwnd);
+    remember(hwnd);
+}
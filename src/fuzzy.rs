@@ -0,0 +1,114 @@
+// src/fuzzy.rs
+
+//! Fuzzy matching of a typed query against window descriptions, as in editor file pickers:
+//! the characters of each word of the query must appear in order, not necessarily next to
+//! each other. Matches that run together or start at word boundaries rank higher.
+
+// Per matched character, on top of which come the bonuses below.
+const MATCH_SCORE: i64 = 1;
+const CONSECUTIVE_BONUS: i64 = 5;
+const WORD_START_BONUS: i64 = 8;
+// Per skipped character between the first and last match of a word.
+const GAP_PENALTY: i64 = 1;
+
+/// How well `query` matches `candidate`, ignoring case; higher is better. None if some
+/// word of the query doesn't match at all. An empty query matches everything equally.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    query
+        .to_lowercase()
+        .split_whitespace()
+        .map(|word| score_word(word, &candidate))
+        .sum()
+}
+
+fn score_word(word: &str, candidate: &[char]) -> Option<i64> {
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous: Option<usize> = None;
+    let mut first = None;
+    for c in word.chars() {
+        let found = next + candidate[next..].iter().position(|&other| other == c)?;
+        score += MATCH_SCORE;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += CONSECUTIVE_BONUS;
+        }
+        if found == 0 || !candidate[found - 1].is_alphanumeric() {
+            score += WORD_START_BONUS;
+        }
+        first.get_or_insert(found);
+        previous = Some(found);
+        next = found + 1;
+    }
+    let spread = match (first, previous) {
+        (Some(first), Some(last)) => last - first + 1,
+        _ => 0,
+    };
+    let gaps = spread.saturating_sub(word.chars().count()) as i64;
+    Some(score - gaps * GAP_PENALTY)
+}
+
+/// The index of the candidate that matches `query` best; the earliest one on a tie.
+pub fn best_match<S: AsRef<str>>(query: &str, candidates: &[S]) -> Option<usize> {
+    let mut best: Option<(usize, i64)> = None;
+    for (idx, candidate) in candidates.iter().enumerate() {
+        if let Some(score) = score(query, candidate.as_ref()) {
+            if best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((idx, score));
+            }
+        }
+    }
+    best.map(|(idx, _)| idx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_bonuses_and_gaps() {
+        let cases = [
+            // The first at the start of a word, the second right after it.
+            ("ab", "ab", 2 * MATCH_SCORE + WORD_START_BONUS + CONSECUTIVE_BONUS),
+            ("ab", "x ab", 2 * MATCH_SCORE + WORD_START_BONUS + CONSECUTIVE_BONUS),
+            ("ab", "a-b", 2 * MATCH_SCORE + 2 * WORD_START_BONUS - GAP_PENALTY),
+            ("ab", "axb", 2 * MATCH_SCORE + WORD_START_BONUS - GAP_PENALTY),
+            ("ab", "axxxb", 2 * MATCH_SCORE + WORD_START_BONUS - 3 * GAP_PENALTY),
+            ("b", "xb", MATCH_SCORE),
+            ("AB", "xaB", 2 * MATCH_SCORE + CONSECUTIVE_BONUS),
+        ];
+        for (query, candidate, expected) in cases {
+            assert_eq!(score(query, candidate), Some(expected), "{:?} in {:?}", query, candidate);
+        }
+    }
+
+    #[test]
+    fn scores_each_word_of_the_query_on_its_own() {
+        let candidate = "main.rs - Code.exe";
+        assert_eq!(
+            score("code main", candidate),
+            Some(score("code", candidate).unwrap() + score("main", candidate).unwrap())
+        );
+        // Words needn't appear in the order typed.
+        assert!(score("main code", candidate).is_some());
+        assert_eq!(score("  ", candidate), Some(0));
+    }
+
+    #[test]
+    fn rejects_queries_that_do_not_match() {
+        let cases = [("ba", "ab"), ("abc", "ab"), ("x", ""), ("code xyz", "main.rs - Code.exe")];
+        for (query, candidate) in cases {
+            assert_eq!(score(query, candidate), None, "{:?} in {:?}", query, candidate);
+        }
+        assert_eq!(best_match("xyz", &["main.rs - Code.exe", "Inbox - Mail"]), None);
+    }
+
+    #[test]
+    fn picks_the_best_and_on_ties_the_earliest() {
+        let windows = ["Firefox - Mail", "Inbox - Mail", "Mail"];
+        assert_eq!(best_match("inbox", &windows), Some(1));
+        assert_eq!(best_match("mail", &windows), Some(0));
+        assert_eq!(best_match("", &windows), Some(0));
+        assert_eq!(best_match("mail", &["Gmail", "Mail"]), Some(1));
+    }
+}
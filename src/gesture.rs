@@ -0,0 +1,237 @@
+// src/gesture.rs
+
+//! Turns the presses and releases of a hotkey into taps, long presses and double taps.
+//! A press while the key is already down is auto-repeat and ignored. Keys only wait to
+//! tell gestures apart if their binding has an action for a long press or double tap;
+//! otherwise a press is a tap straight away. Time is passed in, so the tracker works
+//! the same with any clock.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gesture {
+    Tap,
+    /// Held for at least the long press duration.
+    LongPress,
+    /// Pressed again within the double tap interval after a tap.
+    DoubleTap,
+}
+
+/// Which gestures besides a tap a binding has actions for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GestureSet {
+    pub long_press: bool,
+    pub double_tap: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum KeyState {
+    /// Held since `since`. `decided` once a gesture fired for this press.
+    Down { since: Instant, gestures: GestureSet, decided: bool },
+    /// Released after a tap that may still turn into a double tap.
+    AwaitingSecondTap { released_at: Instant },
+}
+
+#[derive(Debug)]
+pub struct GestureTracker {
+    long_press: Duration,
+    double_tap: Duration,
+    keys: HashMap<u32, KeyState>,
+}
+
+impl GestureTracker {
+    pub fn new(long_press: Duration, double_tap: Duration) -> Self {
+        GestureTracker { long_press, double_tap, keys: HashMap::new() }
+    }
+
+    /// Handles a press of the hotkey `id`, whose binding has actions for `gestures`. Call
+    /// `poll` first, so gestures that became due before this press fire ahead of it.
+    pub fn pressed(&mut self, id: u32, now: Instant, gestures: GestureSet) -> Option<Gesture> {
+        match self.keys.get(&id) {
+            Some(KeyState::Down { .. }) => return None,
+            Some(KeyState::AwaitingSecondTap { released_at }) if now.duration_since(*released_at) <= self.double_tap => {
+                self.keys.insert(id, KeyState::Down { since: now, gestures, decided: true });
+                return Some(Gesture::DoubleTap);
+            }
+            _ => {}
+        }
+        // Nothing to tell apart, so don't keep the user waiting.
+        let immediate = gestures == GestureSet::default();
+        self.keys.insert(id, KeyState::Down { since: now, gestures, decided: immediate });
+        immediate.then_some(Gesture::Tap)
+    }
+
+    /// Handles a release of the hotkey `id`.
+    pub fn released(&mut self, id: u32, now: Instant) -> Option<Gesture> {
+        let Some(KeyState::Down { since, gestures, decided }) = self.keys.get(&id).copied() else {
+            return None;
+        };
+        if decided {
+            self.keys.remove(&id);
+            return None;
+        }
+        if gestures.long_press && now.duration_since(since) >= self.long_press {
+            self.keys.remove(&id);
+            return Some(Gesture::LongPress);
+        }
+        if gestures.double_tap {
+            self.keys.insert(id, KeyState::AwaitingSecondTap { released_at: now });
+            return None;
+        }
+        self.keys.remove(&id);
+        Some(Gesture::Tap)
+    }
+
+    /// Fires gestures decided by time passing alone: long presses of keys still held, and
+    /// taps that weren't followed by a second one in time.
+    pub fn poll(&mut self, now: Instant) -> Vec<(u32, Gesture)> {
+        let mut fired = Vec::new();
+        for (id, state) in self.keys.iter_mut() {
+            match *state {
+                KeyState::Down { since, gestures, decided: false }
+                    if gestures.long_press && now.duration_since(since) >= self.long_press =>
+                {
+                    *state = KeyState::Down { since, gestures, decided: true };
+                    fired.push((*id, Gesture::LongPress));
+                }
+                KeyState::AwaitingSecondTap { released_at } if now.duration_since(released_at) > self.double_tap => {
+                    fired.push((*id, Gesture::Tap));
+                }
+                _ => {}
+            }
+        }
+        self.keys.retain(|_, state| match state {
+            KeyState::AwaitingSecondTap { released_at } => now.duration_since(*released_at) <= self.double_tap,
+            KeyState::Down { .. } => true,
+        });
+        fired
+    }
+
+    /// When `poll` should next be called, if any gesture is waiting on time.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.keys
+            .values()
+            .filter_map(|state| match *state {
+                KeyState::Down { since, gestures, decided: false } if gestures.long_press => Some(since + self.long_press),
+                KeyState::AwaitingSecondTap { released_at } => Some(released_at + self.double_tap + Duration::from_millis(1)),
+                _ => None,
+            })
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONG_PRESS: Duration = Duration::from_millis(500);
+    const DOUBLE_TAP: Duration = Duration::from_millis(300);
+    const KEY: u32 = 7;
+    const ALL: GestureSet = GestureSet { long_press: true, double_tap: true };
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn tracker() -> GestureTracker {
+        GestureTracker::new(LONG_PRESS, DOUBLE_TAP)
+    }
+
+    #[test]
+    fn taps_right_away_without_other_gestures() {
+        let start = Instant::now();
+        let mut gestures = tracker();
+        assert_eq!(gestures.pressed(KEY, start, GestureSet::default()), Some(Gesture::Tap));
+        assert_eq!(gestures.next_deadline(), None);
+        assert_eq!(gestures.released(KEY, start + ms(900)), None);
+    }
+
+    #[test]
+    fn ignores_auto_repeat() {
+        let start = Instant::now();
+        let mut gestures = tracker();
+        assert_eq!(gestures.pressed(KEY, start, GestureSet::default()), Some(Gesture::Tap));
+        assert_eq!(gestures.pressed(KEY, start + ms(30), GestureSet::default()), None);
+        assert_eq!(gestures.pressed(KEY, start + ms(60), GestureSet::default()), None);
+        assert_eq!(gestures.released(KEY, start + ms(90)), None);
+        // Auto-repeat doesn't restart the hold either.
+        let long_press = GestureSet { long_press: true, double_tap: false };
+        assert_eq!(gestures.pressed(KEY, start + ms(100), long_press), None);
+        assert_eq!(gestures.pressed(KEY, start + ms(400), long_press), None);
+        assert_eq!(gestures.poll(start + ms(600)), [(KEY, Gesture::LongPress)]);
+    }
+
+    #[test]
+    fn fires_a_long_press_while_held() {
+        let start = Instant::now();
+        let mut gestures = tracker();
+        assert_eq!(gestures.pressed(KEY, start, ALL), None);
+        assert_eq!(gestures.next_deadline(), Some(start + LONG_PRESS));
+        assert_eq!(gestures.poll(start + LONG_PRESS - ms(1)), []);
+        assert_eq!(gestures.poll(start + LONG_PRESS), [(KEY, Gesture::LongPress)]);
+        // Once only, and the release is part of the same gesture.
+        assert_eq!(gestures.poll(start + ms(900)), []);
+        assert_eq!(gestures.next_deadline(), None);
+        assert_eq!(gestures.released(KEY, start + ms(1000)), None);
+    }
+
+    #[test]
+    fn fires_a_long_press_on_release_when_not_polled_in_time() {
+        let start = Instant::now();
+        let mut gestures = tracker();
+        assert_eq!(gestures.pressed(KEY, start, ALL), None);
+        assert_eq!(gestures.released(KEY, start + ms(700)), Some(Gesture::LongPress));
+        assert_eq!(gestures.next_deadline(), None);
+    }
+
+    #[test]
+    fn a_second_tap_in_time_is_a_double_tap() {
+        let start = Instant::now();
+        let mut gestures = tracker();
+        assert_eq!(gestures.pressed(KEY, start, ALL), None);
+        assert_eq!(gestures.released(KEY, start + ms(50)), None);
+        assert_eq!(gestures.next_deadline(), Some(start + ms(50) + DOUBLE_TAP + ms(1)));
+        assert_eq!(gestures.pressed(KEY, start + ms(50) + DOUBLE_TAP, ALL), Some(Gesture::DoubleTap));
+        assert_eq!(gestures.released(KEY, start + ms(400)), None);
+        assert_eq!(gestures.poll(start + ms(2000)), []);
+    }
+
+    #[test]
+    fn a_second_tap_too_late_is_another_tap() {
+        let start = Instant::now();
+        let mut gestures = tracker();
+        assert_eq!(gestures.pressed(KEY, start, ALL), None);
+        assert_eq!(gestures.released(KEY, start + ms(50)), None);
+        let late = start + ms(50) + DOUBLE_TAP + ms(1);
+        assert_eq!(gestures.poll(late), [(KEY, Gesture::Tap)]);
+        assert_eq!(gestures.next_deadline(), None);
+        assert_eq!(gestures.pressed(KEY, late, ALL), None);
+        assert_eq!(gestures.released(KEY, late + ms(50)), None);
+    }
+
+    #[test]
+    fn taps_on_release_without_double_taps() {
+        let start = Instant::now();
+        let mut gestures = tracker();
+        let long_press = GestureSet { long_press: true, double_tap: false };
+        assert_eq!(gestures.pressed(KEY, start, long_press), None);
+        assert_eq!(gestures.released(KEY, start + ms(100)), Some(Gesture::Tap));
+        assert_eq!(gestures.next_deadline(), None);
+    }
+
+    #[test]
+    fn the_next_deadline_is_the_earliest_of_all_keys() {
+        let start = Instant::now();
+        let mut gestures = tracker();
+        assert_eq!(gestures.pressed(1, start, ALL), None);
+        assert_eq!(gestures.pressed(2, start + ms(100), ALL), None);
+        assert_eq!(gestures.released(2, start + ms(150)), None);
+        // Key 2 awaits a second tap until 451ms, before key 1 becomes a long press at 500ms.
+        assert_eq!(gestures.next_deadline(), Some(start + ms(451)));
+        assert_eq!(gestures.poll(start + ms(451)), [(2, Gesture::Tap)]);
+        assert_eq!(gestures.next_deadline(), Some(start + LONG_PRESS));
+    }
+}
@@ -0,0 +1,363 @@
+// src/hotkeys.rs
+
+//! The global hotkeys and the commands they trigger. Every binding is registered on its
+//! own, so a key combination already taken by another application only costs that one
+//! binding. Bindings that failed keep the reason, for the status report, and are retried
+//! from time to time in case the other application let go of them.
+
+use global_hotkey::{
+    hotkey::{Code, HotKey, Modifiers},
+    GlobalHotKeyManager,
+};
+use log::{info, warn};
+
+use crate::command::{Command, DesktopTarget};
+use crate::config::BindingConfig;
+use crate::error::DeskError;
+use crate::gesture::{Gesture, GestureSet};
+use crate::modal::ModeKey;
+
+/// The commands a binding triggers, one per gesture.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GestureActions {
+    pub tap: Option<Command>,
+    pub long_press: Option<Command>,
+    pub double_tap: Option<Command>,
+}
+
+impl GestureActions {
+    pub fn tap(command: Command) -> Self {
+        GestureActions { tap: Some(command), ..Default::default() }
+    }
+
+    /// The gestures a press has to be told apart from a tap for.
+    pub fn gestures(&self) -> GestureSet {
+        GestureSet { long_press: self.long_press.is_some(), double_tap: self.double_tap.is_some() }
+    }
+
+    pub fn command(&self, gesture: Gesture) -> Option<Command> {
+        match gesture {
+            Gesture::Tap => self.tap.clone(),
+            Gesture::LongPress => self.long_press.clone(),
+            Gesture::DoubleTap => self.double_tap.clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for GestureActions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(command) = &self.tap {
+            parts.push(command.to_string());
+        }
+        if let Some(command) = &self.long_press {
+            parts.push(format!("hold: {}", command));
+        }
+        if let Some(command) = &self.double_tap {
+            parts.push(format!("double: {}", command));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindingStatus {
+    Registered,
+    /// Released while hotkeys are paused.
+    Paused,
+    Failed(DeskError),
+    /// Uses the same keys as the binding with this label, which was registered first.
+    Duplicate(String),
+}
+
+struct Binding {
+    /// The keys as shown to the user, e.g. "RCtrl+3".
+    label: String,
+    /// None if the configured keys couldn't be parsed.
+    hotkey: Option<HotKey>,
+    actions: GestureActions,
+    /// Stays registered while hotkeys are paused, e.g. the binding that resumes them.
+    always_active: bool,
+    status: BindingStatus,
+}
+
+pub struct HotkeyRegistry {
+    manager: GlobalHotKeyManager,
+    bindings: Vec<Binding>,
+    paused: bool,
+    /// Keys registered only while a mode is active.
+    grabbed: Vec<HotKey>,
+}
+
+impl HotkeyRegistry {
+    pub fn new(manager: GlobalHotKeyManager) -> Self {
+        HotkeyRegistry { manager, bindings: Vec::new(), paused: false, grabbed: Vec::new() }
+    }
+
+    /// Adds a binding and registers it right away. Failures are recorded, not returned.
+    pub fn add(&mut self, label: &str, hotkey: Result<HotKey, DeskError>, actions: GestureActions, always_active: bool) {
+        let (hotkey, status) = match hotkey {
+            Ok(hotkey) => {
+                let duplicate_of = self
+                    .bindings
+                    .iter()
+                    .find(|other| other.hotkey.map(|h| h.id()) == Some(hotkey.id()))
+                    .map(|other| other.label.clone());
+                let status = match duplicate_of {
+                    Some(other) => BindingStatus::Duplicate(other),
+                    None if self.paused && !always_active => BindingStatus::Paused,
+                    None => self.register(label, hotkey),
+                };
+                (Some(hotkey), status)
+            }
+            Err(e) => (None, BindingStatus::Failed(e)),
+        };
+        match &status {
+            BindingStatus::Registered => info!("Registered {} -> {}", label, actions),
+            BindingStatus::Paused => info!("Added {} -> {} (paused)", label, actions),
+            BindingStatus::Failed(e) => warn!("Could not register {} -> {}: {}", label, actions, e),
+            BindingStatus::Duplicate(other) => warn!("{} -> {} uses the same keys as {}; ignored", label, actions, other),
+        }
+        self.bindings.push(Binding { label: label.to_string(), hotkey, actions, always_active, status });
+    }
+
+    fn register(&self, label: &str, hotkey: HotKey) -> BindingStatus {
+        match self.manager.register(hotkey) {
+            Ok(()) => BindingStatus::Registered,
+            Err(e) => BindingStatus::Failed(DeskError::from_hotkey_error(label, e)),
+        }
+    }
+
+    /// The actions bound to a hotkey. The first binding with these keys is the one that
+    /// got registered; later ones are duplicates.
+    pub fn actions_for(&self, hotkey_id: u32) -> Option<GestureActions> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.hotkey.map(|h| h.id()) == Some(hotkey_id))
+            .map(|binding| binding.actions.clone())
+    }
+
+    fn has_hotkey(&self, hotkey: HotKey) -> bool {
+        self.actions_for(hotkey.id()).is_some()
+    }
+
+    /// Registers plain keys for the duration of a mode. Keys a binding already uses are
+    /// left to it. Returns how many keys couldn't be registered.
+    pub fn grab(&mut self, hotkeys: &[HotKey]) -> usize {
+        let mut failed = 0;
+        for hotkey in hotkeys {
+            if self.has_hotkey(*hotkey) || self.grabbed.contains(hotkey) {
+                continue;
+            }
+            match self.manager.register(*hotkey) {
+                Ok(()) => self.grabbed.push(*hotkey),
+                Err(e) => {
+                    warn!("Could not grab {:?} for the mode: {:?}", hotkey, e);
+                    failed += 1;
+                }
+            }
+        }
+        failed
+    }
+
+    /// Unregisters the keys taken by `grab`, so they type normally again.
+    pub fn release_grabbed(&mut self) {
+        for hotkey in self.grabbed.drain(..) {
+            if let Err(e) = self.manager.unregister(hotkey) {
+                warn!("Failed to release {:?}: {:?}", hotkey, e);
+            }
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Unregisters (or re-registers) every binding that isn't always active, so their key
+    /// combinations reach other applications while paused.
+    pub fn set_paused(&mut self, paused: bool) {
+        for i in 0..self.bindings.len() {
+            let binding = &self.bindings[i];
+            let Some(hotkey) = binding.hotkey else { continue };
+            if binding.always_active {
+                continue;
+            }
+            let status = match (&binding.status, paused) {
+                (BindingStatus::Registered, true) => {
+                    if let Err(e) = self.manager.unregister(hotkey) {
+                        warn!("Failed to unregister hotkey {}: {:?}", binding.label, e);
+                    }
+                    BindingStatus::Paused
+                }
+                (BindingStatus::Paused, false) => self.register(&binding.label, hotkey),
+                _ => continue,
+            };
+            if let BindingStatus::Failed(e) = &status {
+                warn!("Failed to re-register hotkey {}: {}", binding.label, e);
+            }
+            self.bindings[i].status = status;
+        }
+        self.paused = paused;
+        info!("Hotkeys {}.", if paused { "paused" } else { "resumed" });
+    }
+
+    /// Tries again to register bindings whose keys were taken. Returns how many succeeded.
+    pub fn retry_failed(&mut self) -> usize {
+        let mut recovered = 0;
+        for i in 0..self.bindings.len() {
+            let binding = &self.bindings[i];
+            let Some(hotkey) = binding.hotkey else { continue };
+            if !matches!(binding.status, BindingStatus::Failed(DeskError::HotkeyTaken(_))) {
+                continue;
+            }
+            let status = if self.paused && !binding.always_active {
+                BindingStatus::Paused
+            } else {
+                self.register(&binding.label, hotkey)
+            };
+            if !matches!(status, BindingStatus::Failed(_)) {
+                info!("Hotkey {} is available again -> {}", binding.label, binding.actions);
+                recovered += 1;
+            }
+            self.bindings[i].status = status;
+        }
+        recovered
+    }
+
+    pub fn failed_count(&self) -> usize {
+        self.bindings
+            .iter()
+            .filter(|binding| matches!(binding.status, BindingStatus::Failed(_) | BindingStatus::Duplicate(_)))
+            .count()
+    }
+
+    /// Whether any failed binding may still be registered by `retry_failed`.
+    pub fn has_retryable(&self) -> bool {
+        self.bindings
+            .iter()
+            .any(|binding| matches!(binding.status, BindingStatus::Failed(DeskError::HotkeyTaken(_))))
+    }
+
+    /// One line per binding: its keys, its command and whether it works.
+    pub fn report(&self) -> String {
+        let lines: Vec<String> = self
+            .bindings
+            .iter()
+            .map(|binding| {
+                let status = match &binding.status {
+                    BindingStatus::Registered => "active".to_string(),
+                    BindingStatus::Paused => "paused".to_string(),
+                    BindingStatus::Failed(DeskError::HotkeyTaken(_)) => "in use by another application".to_string(),
+                    BindingStatus::Failed(e) => e.to_string(),
+                    BindingStatus::Duplicate(other) => format!("same keys as {}", other),
+                };
+                format!("{:<16} {:<16} {}", binding.label, binding.actions.to_string(), status)
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    /// A short summary of the failed bindings, for a notification.
+    pub fn failure_summary(&self) -> Option<String> {
+        let failed: Vec<&str> = self
+            .bindings
+            .iter()
+            .filter(|binding| matches!(binding.status, BindingStatus::Failed(_) | BindingStatus::Duplicate(_)))
+            .map(|binding| binding.label.as_str())
+            .collect();
+        if failed.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "{} of {} hotkeys are unavailable: {}.\nRun 'deskhopper hotkeys' or use the tray menu for details.",
+            failed.len(),
+            self.bindings.len(),
+            failed.join(", ")
+        ))
+    }
+}
+
+/// Adds the bindings from the config file. Must come before the built-in ones, which
+/// they replace.
+pub fn register_configured_bindings(registry: &mut HotkeyRegistry, bindings: &[BindingConfig]) {
+    for binding in bindings {
+        let actions = GestureActions {
+            tap: binding.tap.clone().map(|spec| spec.0),
+            long_press: binding.long_press.clone().map(|spec| spec.0),
+            double_tap: binding.double_tap.clone().map(|spec| spec.0),
+        };
+        let hotkey = if actions == GestureActions::default() {
+            Err(DeskError::InvalidHotkey(format!("{}: no tap, long_press or double_tap command", binding.keys)))
+        } else {
+            parse_hotkey(&binding.keys)
+        };
+        registry.add(&binding.keys, hotkey, actions, false);
+    }
+}
+
+/// Adds the built-in switch (RCtrl+N) and move (RCtrl+Shift+N) bindings for desktops 1-10,
+/// and the configured pause toggle. Keys already bound in the config file are skipped.
+pub fn register_default_bindings(registry: &mut HotkeyRegistry, pause_toggle: &str) {
+    info!("Registering hotkeys...");
+    for (desktop_idx, (digit, code)) in DIGIT_KEYS.iter().enumerate() {
+        add_default(
+            registry,
+            &format!("RCtrl+{}", digit),
+            HotKey::new(Some(Modifiers::CONTROL), *code),
+            Command::SwitchDesktop(DesktopTarget::Index(desktop_idx)),
+        );
+    }
+    for (desktop_idx, (digit, code)) in DIGIT_KEYS.iter().enumerate() {
+        add_default(
+            registry,
+            &format!("RCtrl+Shift+{}", digit),
+            HotKey::new(Some(Modifiers::CONTROL | Modifiers::SHIFT), *code),
+            Command::MoveActiveWindow(DesktopTarget::Index(desktop_idx)),
+        );
+    }
+
+    if pause_toggle.is_empty() {
+        info!("Pause hotkey disabled in configuration.");
+    } else {
+        // Stays active while the others are paused, so it can resume them.
+        let actions = GestureActions::tap(Command::TogglePauseHotkeys);
+        registry.add(pause_toggle, parse_hotkey(pause_toggle), actions, true);
+    }
+    info!("All hotkeys registration attempted.");
+}
+
+/// Adds the leader hotkey, which enters desktop mode, unless it is disabled.
+pub fn register_leader(registry: &mut HotkeyRegistry, leader: &str) {
+    if leader.is_empty() {
+        info!("Leader hotkey disabled in configuration.");
+        return;
+    }
+    registry.add(leader, parse_hotkey(leader), GestureActions::tap(Command::EnterMode), false);
+}
+
+/// The hotkey standing for `key` while a mode is active: the key alone, without modifiers.
+pub fn mode_hotkey(key: ModeKey) -> Result<HotKey, DeskError> {
+    match key {
+        ModeKey::Escape => parse_hotkey("escape"),
+        ModeKey::Char(c) => parse_hotkey(&c.to_string()),
+    }
+}
+
+fn add_default(registry: &mut HotkeyRegistry, label: &str, hotkey: HotKey, command: Command) {
+    if registry.has_hotkey(hotkey) {
+        info!("{} is bound in the config file; skipping its default binding.", label);
+        return;
+    }
+    registry.add(label, Ok(hotkey), GestureActions::tap(command), false);
+}
+
+fn parse_hotkey(keys: &str) -> Result<HotKey, DeskError> {
+    keys.parse::<HotKey>()
+        .map_err(|e| DeskError::InvalidHotkey(format!("{}: {}", keys, e)))
+}
+
+// Keys for desktops 1-10, in order; 0 stands for desktop 10.
+const DIGIT_KEYS: [(u32, Code); 10] = [
+    (1, Code::Digit1), (2, Code::Digit2), (3, Code::Digit3), (4, Code::Digit4), (5, Code::Digit5),
+    (6, Code::Digit6), (7, Code::Digit7), (8, Code::Digit8), (9, Code::Digit9), (0, Code::Digit0),
+];
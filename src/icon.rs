@@ -0,0 +1,320 @@
+// src/icon.rs
+
+//! Renders the tray icon at runtime: a short label (the desktop number or the start of
+//! its name) drawn with a built-in pixel font over a coloured tile. Everything in here
+//! is platform-neutral and free of side effects, so the output can be compared pixel by
+//! pixel on any machine.
+
+use image::{Rgba, RgbaImage};
+
+/// Side length of rendered icons in pixels. Windows scales this down for the tray as needed.
+pub const ICON_SIZE: u32 = 32;
+
+/// Longest label that still fits the icon legibly.
+pub const MAX_LABEL_CHARS: usize = 3;
+
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+const PADDING: u32 = 2;
+const CORNER_RADIUS: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IconStyle {
+    pub background: Rgba<u8>,
+    pub foreground: Rgba<u8>,
+}
+
+/// 3x5 glyphs, one row per byte, using the low three bits (MSB on the left).
+fn glyph(c: char) -> Option<[u8; 5]> {
+    let rows = match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b011, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        _ => return None,
+    };
+    Some(rows)
+}
+
+/// Returns the first `max_chars` characters of `text` that can be drawn, uppercased.
+pub fn renderable_label(text: &str, max_chars: usize) -> String {
+    text.chars()
+        .filter(|c| glyph(*c).is_some())
+        .map(|c| c.to_ascii_uppercase())
+        .take(max_chars.min(MAX_LABEL_CHARS))
+        .collect()
+}
+
+/// Renders `label` centred on a rounded tile. Characters without a glyph are skipped,
+/// and anything past `MAX_LABEL_CHARS` is dropped.
+pub fn render_label_icon(label: &str, style: &IconStyle) -> RgbaImage {
+    let mut image = RgbaImage::from_pixel(ICON_SIZE, ICON_SIZE, style.background);
+    clear_corners(&mut image);
+
+    let glyphs: Vec<[u8; 5]> = label.chars().filter_map(glyph).take(MAX_LABEL_CHARS).collect();
+    if glyphs.is_empty() {
+        return image;
+    }
+
+    // Width in font units: glyphs plus a one-unit gap between each pair.
+    let count = glyphs.len() as u32;
+    let units_wide = count * GLYPH_WIDTH + (count - 1);
+    let available = ICON_SIZE - 2 * PADDING;
+    let scale = (available / units_wide).min(available / GLYPH_HEIGHT).max(1);
+
+    let text_width = units_wide * scale;
+    let text_height = GLYPH_HEIGHT * scale;
+    let origin_x = (ICON_SIZE - text_width) / 2;
+    let origin_y = (ICON_SIZE - text_height) / 2;
+
+    for (n, rows) in glyphs.iter().enumerate() {
+        let glyph_x = origin_x + n as u32 * (GLYPH_WIDTH + 1) * scale;
+        for (row, bits) in rows.iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+                let x0 = glyph_x + col * scale;
+                let y0 = origin_y + row as u32 * scale;
+                for y in y0..y0 + scale {
+                    for x in x0..x0 + scale {
+                        image.put_pixel(x, y, style.foreground);
+                    }
+                }
+            }
+        }
+    }
+    image
+}
+
+/// Turns `image` into a faded greyscale version of itself, used while hotkeys are paused.
+pub fn dim(image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        let luma = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+        let grey = (luma / 2 + 64) as u8;
+        *pixel = Rgba([grey, grey, grey, a]);
+    }
+}
+
+/// Makes the pixels outside the rounded corners transparent.
+fn clear_corners(image: &mut RgbaImage) {
+    let transparent = Rgba([0, 0, 0, 0]);
+    let r = CORNER_RADIUS as i64;
+    let max = ICON_SIZE as i64 - 1;
+    for y in 0..r {
+        for x in 0..r {
+            // Offset from the centre of the corner's circle.
+            let dx = r - x;
+            let dy = r - y;
+            if dx * dx + dy * dy > r * r {
+                for (px, py) in [(x, y), (max - x, y), (x, max - y), (max - x, max - y)] {
+                    image.put_pixel(px as u32, py as u32, transparent);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STYLE: IconStyle = IconStyle { background: Rgba([0, 0, 255, 255]), foreground: Rgba([255, 255, 255, 255]) };
+
+    // One character per pixel: '#' foreground, '.' background, ' ' transparent.
+    fn picture(image: &RgbaImage) -> Vec<String> {
+        image
+            .rows()
+            .map(|row| {
+                row.map(|pixel| match *pixel {
+                    p if p[3] == 0 => ' ',
+                    p if p == STYLE.foreground => '#',
+                    p if p == STYLE.background => '.',
+                    p => panic!("unexpected pixel {:?}", p),
+                })
+                .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn renders_one_digit() {
+        let expected = [
+        "     ......................     ",
+        "  ............................  ",
+        " .............................. ",
+        " ............#####............. ",
+        " ............#####............. ",
+        ".............#####..............",
+        ".............#####..............",
+        ".............#####..............",
+        "........##########..............",
+        "........##########..............",
+        "........##########..............",
+        "........##########..............",
+        "........##########..............",
+        ".............#####..............",
+        ".............#####..............",
+        ".............#####..............",
+        ".............#####..............",
+        ".............#####..............",
+        ".............#####..............",
+        ".............#####..............",
+        ".............#####..............",
+        ".............#####..............",
+        ".............#####..............",
+        "........###############.........",
+        "........###############.........",
+        "........###############.........",
+        "........###############.........",
+        " .......###############........ ",
+        " .............................. ",
+        " .............................. ",
+        "  ............................  ",
+        "     ......................     ",
+        ];
+        assert_eq!(picture(&render_label_icon("1", &STYLE)), expected);
+    }
+
+    #[test]
+    fn renders_two_digits() {
+        let expected = [
+        "     ......................     ",
+        "  ............................  ",
+        " .............................. ",
+        " .............................. ",
+        " .............................. ",
+        "................................",
+        "......####........############..",
+        "......####........############..",
+        "......####........############..",
+        "......####........############..",
+        "..########........####....####..",
+        "..########........####....####..",
+        "..########........####....####..",
+        "..########........####....####..",
+        "......####........####....####..",
+        "......####........####....####..",
+        "......####........####....####..",
+        "......####........####....####..",
+        "......####........####....####..",
+        "......####........####....####..",
+        "......####........####....####..",
+        "......####........####....####..",
+        "..############....############..",
+        "..############....############..",
+        "..############....############..",
+        "..############....############..",
+        "................................",
+        " .............................. ",
+        " .............................. ",
+        " .............................. ",
+        "  ............................  ",
+        "     ......................     ",
+        ];
+        assert_eq!(picture(&render_label_icon("10", &STYLE)), expected);
+    }
+
+    #[test]
+    fn renders_letters() {
+        let expected = [
+        "     ......................     ",
+        "  ............................  ",
+        " .............................. ",
+        " .............................. ",
+        " .............................. ",
+        "................................",
+        "..####....####........####......",
+        "..####....####........####......",
+        "..####....####........####......",
+        "..####....####........####......",
+        "..############....####....####..",
+        "..############....####....####..",
+        "..############....####....####..",
+        "..############....####....####..",
+        "..############....############..",
+        "..############....############..",
+        "..############....############..",
+        "..############....############..",
+        "..####....####....####....####..",
+        "..####....####....####....####..",
+        "..####....####....####....####..",
+        "..####....####....####....####..",
+        "..####....####....####....####..",
+        "..####....####....####....####..",
+        "..####....####....####....####..",
+        "..####....####....####....####..",
+        "................................",
+        " .............................. ",
+        " .............................. ",
+        " .............................. ",
+        "  ............................  ",
+        "     ......................     ",
+        ];
+        assert_eq!(picture(&render_label_icon("MA", &STYLE)), expected);
+        assert_eq!(render_label_icon("ma", &STYLE), render_label_icon("MA", &STYLE));
+    }
+
+    #[test]
+    fn corners_are_transparent() {
+        let image = render_label_icon("8", &STYLE);
+        let max = ICON_SIZE - 1;
+        for (x, y) in [(0, 0), (max, 0), (0, max), (max, max)] {
+            assert_eq!(*image.get_pixel(x, y), Rgba([0, 0, 0, 0]), "corner at ({}, {})", x, y);
+        }
+        // The edges between the corners are part of the tile.
+        assert_eq!(*image.get_pixel(ICON_SIZE / 2, 0), STYLE.background);
+        assert_eq!(*image.get_pixel(0, ICON_SIZE / 2), STYLE.background);
+    }
+
+    #[test]
+    fn skips_characters_without_a_glyph() {
+        assert_eq!(render_label_icon("1-0", &STYLE), render_label_icon("10", &STYLE));
+        assert_eq!(picture(&render_label_icon("", &STYLE)), picture(&render_label_icon("?", &STYLE)));
+        assert_eq!(renderable_label("web-dev 2", 3), "WEB");
+    }
+
+    #[test]
+    fn dim_greys_out_and_keeps_transparency() {
+        let mut image = render_label_icon("1", &STYLE);
+        dim(&mut image);
+        // White text: luma 255 -> 127 + 64.
+        assert_eq!(*image.get_pixel(15, 10), Rgba([191, 191, 191, 255]));
+        // Blue tile: luma 29 -> 14 + 64.
+        assert_eq!(*image.get_pixel(2, 16), Rgba([78, 78, 78, 255]));
+        assert_eq!(image.get_pixel(0, 0)[3], 0);
+    }
+}
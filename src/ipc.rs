@@ -0,0 +1,295 @@
+// src/ipc.rs
+
+//! Local IPC over a named pipe per user and logon session, which only that user may open.
+//! A client connects, writes one command line
+//! (see `command::COMMAND_USAGE`), and reads back a status line: `ok`, or
+//! `error: <kind>: <message>`, where `<kind>` is one of `DeskError::kind` or `invalid-command`
+//! for a line that didn't parse. Commands that report something follow `ok` with their
+//! output; the reply ends when the pipe is closed. Running `deskhopper <command>` acts as
+//! such a client.
+
+use anyhow::{Context, Result};
+use log::{debug, error, info, warn};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Read, Write},
+    os::windows::io::{AsRawHandle, FromRawHandle},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+use tao::event_loop::EventLoopProxy;
+use windows::{
+    core::{HRESULT, PCWSTR, PWSTR},
+    Win32::{
+        Foundation::{CloseHandle, LocalFree, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED, HANDLE, HLOCAL},
+        Security::{
+            Authorization::{ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1},
+            GetTokenInformation, TokenSessionId, TokenUser, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES, TOKEN_QUERY,
+            TOKEN_USER,
+        },
+        Storage::FileSystem::{FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX},
+        System::Console::{AttachConsole, ATTACH_PARENT_PROCESS},
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE,
+            PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+        },
+        System::Threading::{GetCurrentProcess, OpenProcessToken},
+    },
+};
+
+use crate::{
+    command::{Command, COMMAND_USAGE},
+    error::DeskError,
+    CustomEvent,
+};
+
+const PIPE_BUFFER_SIZE: u32 = 4096;
+// How long a request waits for the event loop to handle it.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECT_ATTEMPTS: u32 = 10;
+const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+pub const REPLY_OK: &str = "ok";
+
+pub fn ok_reply(output: Option<String>) -> String {
+    match output {
+        Some(output) => format!("{}\n{}", REPLY_OK, output),
+        None => REPLY_OK.to_string(),
+    }
+}
+
+pub fn error_reply(error: &DeskError) -> String {
+    format!("error: {}: {}", error.kind(), error)
+}
+
+/// An IPC request on its way to the event loop, with the channel the reply goes back on.
+#[derive(Debug, Clone)]
+pub struct IpcRequest {
+    pub command: Command,
+    pub reply_to: mpsc::Sender<String>,
+}
+
+/// Who this process runs as: the user's SID and the logon session.
+struct Identity {
+    sid: String,
+    session_id: u32,
+}
+
+fn current_identity() -> Result<Identity> {
+    let mut token = HANDLE::default();
+    unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) }.context("OpenProcessToken failed")?;
+    let identity = unsafe { token_identity(token) };
+    let _ = unsafe { CloseHandle(token) };
+    identity
+}
+
+unsafe fn token_identity(token: HANDLE) -> Result<Identity> {
+    let mut size = 0;
+    // Fails, but reports the size the user's SID needs.
+    let _ = GetTokenInformation(token, TokenUser, None, 0, &mut size);
+    // u64s keep TOKEN_USER aligned.
+    let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+    GetTokenInformation(token, TokenUser, Some(buffer.as_mut_ptr().cast()), size, &mut size)
+        .context("GetTokenInformation(TokenUser) failed")?;
+    let user = &*(buffer.as_ptr() as *const TOKEN_USER);
+    let mut sid = PWSTR::null();
+    ConvertSidToStringSidW(user.User.Sid, &mut sid).context("ConvertSidToStringSidW failed")?;
+    let sid_string = sid.to_string();
+    let _ = LocalFree(HLOCAL(sid.0.cast()));
+
+    let mut session_id = 0u32;
+    GetTokenInformation(
+        token,
+        TokenSessionId,
+        Some((&mut session_id as *mut u32).cast()),
+        std::mem::size_of::<u32>() as u32,
+        &mut size,
+    )
+    .context("GetTokenInformation(TokenSessionId) failed")?;
+    Ok(Identity { sid: sid_string.context("The user's SID is not valid UTF-16")?, session_id })
+}
+
+fn pipe_name(identity: &Identity) -> String {
+    // Per user and session, so other users and the same user's other sessions don't talk to each other.
+    format!(r"\\.\pipe\deskhopper-{}-{}", identity.sid, identity.session_id)
+}
+
+/// A security descriptor that lets only one user open the pipe, freed on drop.
+struct UserOnly(PSECURITY_DESCRIPTOR);
+
+impl UserOnly {
+    fn new(sid: &str) -> Result<Self> {
+        // A protected DACL, so nothing is inherited, with full access for the user alone.
+        let sddl: Vec<u16> = format!("D:P(A;;GA;;;{})", sid).encode_utf16().chain(std::iter::once(0)).collect();
+        let mut descriptor = PSECURITY_DESCRIPTOR(std::ptr::null_mut());
+        unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(PCWSTR(sddl.as_ptr()), SDDL_REVISION_1, &mut descriptor, None)
+        }
+        .context("Failed to build the IPC pipe's security descriptor")?;
+        Ok(UserOnly(descriptor))
+    }
+}
+
+impl Drop for UserOnly {
+    fn drop(&mut self) {
+        let _ = unsafe { LocalFree(HLOCAL(self.0 .0)) };
+    }
+}
+
+/// Starts the thread that accepts IPC connections and forwards their commands to the
+/// event loop, one connection at a time.
+pub fn spawn_server(proxy: EventLoopProxy<CustomEvent>) {
+    thread::spawn(move || {
+        if let Err(e) = run_server(&proxy) {
+            error!("IPC server stopped: {:?}", e);
+        }
+        info!("IPC server thread finished.");
+    });
+}
+
+fn run_server(proxy: &EventLoopProxy<CustomEvent>) -> Result<()> {
+    let identity = current_identity().context("Failed to look up the current user")?;
+    let name = pipe_name(&identity);
+    let security = UserOnly::new(&identity.sid)?;
+    // Fails if another process, possibly one squatting on the name, created the pipe first.
+    let mut listening = create_pipe(&name, &security, true)?;
+    info!("IPC server thread started on {}", name);
+    loop {
+        accept_connection(&listening)?;
+        // An instance stays open while the connection is served, so the name stays ours.
+        let next = create_pipe(&name, &security, false)?;
+        let pipe = std::mem::replace(&mut listening, next);
+        if let Err(e) = serve_connection(pipe, proxy) {
+            warn!("IPC connection failed: {:?}", e);
+        }
+    }
+}
+
+fn create_pipe(name: &str, security: &UserOnly, first: bool) -> Result<File> {
+    let wide_name: Vec<u16> = name.encode_utf16().chain(std::iter::once(0)).collect();
+    let attributes = SECURITY_ATTRIBUTES {
+        nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+        lpSecurityDescriptor: security.0 .0,
+        bInheritHandle: false.into(),
+    };
+    let open_mode = if first { PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE } else { PIPE_ACCESS_DUPLEX };
+    let handle = unsafe {
+        CreateNamedPipeW(
+            PCWSTR(wide_name.as_ptr()),
+            open_mode,
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_UNLIMITED_INSTANCES,
+            PIPE_BUFFER_SIZE,
+            PIPE_BUFFER_SIZE,
+            0,
+            Some(&attributes),
+        )
+    };
+    if handle.is_invalid() {
+        return Err(windows::core::Error::from_win32()).context("CreateNamedPipeW failed");
+    }
+    // Closes the handle on drop.
+    Ok(unsafe { File::from_raw_handle(handle.0) })
+}
+
+// Waits for a client to connect to `pipe`.
+fn accept_connection(pipe: &File) -> Result<()> {
+    if let Err(e) = unsafe { ConnectNamedPipe(HANDLE(pipe.as_raw_handle()), None) } {
+        // A client that connected between create and connect is still a valid connection.
+        if e.code() != HRESULT::from_win32(ERROR_PIPE_CONNECTED.0) {
+            return Err(e).context("ConnectNamedPipe failed");
+        }
+    }
+    Ok(())
+}
+
+fn serve_connection(pipe: File, proxy: &EventLoopProxy<CustomEvent>) -> Result<()> {
+    let mut reader = BufReader::new(pipe);
+    let mut line = String::new();
+    reader.read_line(&mut line).context("Failed to read IPC request")?;
+    let line = line.trim();
+    debug!("IPC request: '{}'", line);
+
+    let reply = match line.parse::<Command>() {
+        Ok(command) => {
+            let (reply_tx, reply_rx) = mpsc::channel();
+            let request = IpcRequest { command, reply_to: reply_tx };
+            if proxy.send_event(CustomEvent::IpcRequest(request)).is_err() {
+                anyhow::bail!("Failed to send IPC request to main loop. Main loop likely exited.");
+            }
+            reply_rx
+                .recv_timeout(REPLY_TIMEOUT)
+                .unwrap_or_else(|_| "error: timeout: timed out waiting for DeskHopper".to_string())
+        }
+        Err(e) => format!("error: invalid-command: {}", e),
+    };
+
+    let mut pipe = reader.into_inner();
+    writeln!(pipe, "{}", reply).context("Failed to write IPC reply")?;
+    // Wait for the client to read the reply before the pipe is closed, which would discard it.
+    pipe.sync_all().context("Failed to flush IPC reply")?;
+    Ok(())
+}
+
+/// Sends one command line to the running instance and returns its reply, status line first.
+pub fn send_request(line: &str) -> Result<String> {
+    let name = pipe_name(&current_identity().context("Failed to look up the current user")?);
+    let mut attempt = 0;
+    let mut pipe = loop {
+        match OpenOptions::new().read(true).write(true).open(&name) {
+            Ok(pipe) => break pipe,
+            // Either every pipe instance is busy, or the server is between two connections.
+            Err(e) if attempt + 1 < CONNECT_ATTEMPTS
+                && (e.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) || e.kind() == std::io::ErrorKind::NotFound) =>
+            {
+                attempt += 1;
+                thread::sleep(CONNECT_RETRY_DELAY);
+            }
+            Err(e) => return Err(e).context("Could not connect to DeskHopper. Is it running?"),
+        }
+    };
+
+    writeln!(pipe, "{}", line).context("Failed to send IPC request")?;
+    let mut reply = String::new();
+    pipe.read_to_string(&mut reply).context("Failed to read IPC reply")?;
+    Ok(reply.trim_end().to_string())
+}
+
+/// Release builds use the windows subsystem and start without a console; this borrows the
+/// one we were launched from so output shows up there. Fails harmlessly if there is none.
+pub fn attach_parent_console() {
+    let _ = unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
+}
+
+/// Entry point for `deskhopper <command>`: sends the command to the running instance
+/// and prints its reply. Exits with status 1 if the command failed.
+pub fn run_client(args: &[String]) -> Result<()> {
+    attach_parent_console();
+
+    let line = args.join(" ");
+    if matches!(line.as_str(), "help" | "--help" | "-h") {
+        println!("Usage: deskhopper [command]\n\n{}", COMMAND_USAGE);
+        return Ok(());
+    }
+    // Validate locally so typos are reported even when DeskHopper isn't running.
+    if let Err(e) = line.parse::<Command>() {
+        eprintln!("error: {}\n\n{}", e, COMMAND_USAGE);
+        std::process::exit(1);
+    }
+    send_and_print(&line)
+}
+
+/// Sends a command line that is known to parse and prints the reply. Exits with status 1
+/// if the command failed.
+pub fn send_and_print(line: &str) -> Result<()> {
+    let reply = send_request(line)?;
+    let (status, output) = reply.split_once('\n').unwrap_or((&reply, ""));
+    if status != REPLY_OK {
+        eprintln!("{}", reply);
+        std::process::exit(1);
+    }
+    // Commands without output just confirm they were done.
+    println!("{}", if output.is_empty() { status } else { output });
+    Ok(())
+}
@@ -0,0 +1,188 @@
+// src/layout.rs
+
+//! The desktops declared in the config file, and the changes that make the actual desktops
+//! match them. Declared desktops are matched by position; one whose name is found further
+//! along is brought into place by swapping, so desktops keep their windows. Desktops past
+//! the declared ones are reported, never removed.
+
+use serde::Deserialize;
+use std::fmt;
+
+/// A desktop as declared in the config file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DesktopSpec {
+    /// An empty name leaves the desktop's name as it is.
+    #[serde(default)]
+    pub name: String,
+    /// Path of the wallpaper image.
+    pub wallpaper: Option<String>,
+}
+
+/// A desktop as it currently is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ActualDesktop {
+    pub name: String,
+    pub wallpaper: String,
+}
+
+/// One step towards the declared layout. Indices are 0-based and refer to the desktops as
+/// they are after the steps before.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutChange {
+    /// Append this many desktops.
+    Create(usize),
+    /// Exchange the windows and names of two desktops.
+    Swap(usize, usize),
+    Rename { desktop_idx: usize, from: String, to: String },
+    SetWallpaper { desktop_idx: usize, path: String },
+    /// A desktop beyond the declared ones. Only reported.
+    Extra { desktop_idx: usize, name: String },
+}
+
+/// Works out the changes that turn `actual` into the declared `specs`.
+pub fn plan(specs: &[DesktopSpec], actual: &[ActualDesktop]) -> Vec<LayoutChange> {
+    let mut changes = Vec::new();
+    let mut desktops = actual.to_vec();
+    if specs.len() > desktops.len() {
+        changes.push(LayoutChange::Create(specs.len() - desktops.len()));
+        desktops.resize(specs.len(), ActualDesktop::default());
+    }
+
+    for (idx, spec) in specs.iter().enumerate() {
+        if !spec.name.is_empty() && !same_name(&desktops[idx].name, &spec.name) {
+            // Desktops before this one are in place already, so only look further along.
+            let found = (idx + 1..desktops.len()).find(|&other| same_name(&desktops[other].name, &spec.name));
+            match found {
+                Some(other) => {
+                    changes.push(LayoutChange::Swap(idx, other));
+                    // Names go along with the windows; wallpapers stay with the desktop.
+                    let name = std::mem::take(&mut desktops[other].name);
+                    desktops[other].name = std::mem::replace(&mut desktops[idx].name, name);
+                }
+                None => {
+                    let from = std::mem::replace(&mut desktops[idx].name, spec.name.clone());
+                    changes.push(LayoutChange::Rename { desktop_idx: idx, from, to: spec.name.clone() });
+                }
+            }
+        }
+        if let Some(path) = &spec.wallpaper {
+            if !path.eq_ignore_ascii_case(&desktops[idx].wallpaper) {
+                changes.push(LayoutChange::SetWallpaper { desktop_idx: idx, path: path.clone() });
+            }
+        }
+    }
+
+    for (idx, desktop) in desktops.iter().enumerate().skip(specs.len()) {
+        changes.push(LayoutChange::Extra { desktop_idx: idx, name: desktop.name.clone() });
+    }
+    changes
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+impl fmt::Display for LayoutChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutChange::Create(1) => write!(f, "create 1 desktop"),
+            LayoutChange::Create(count) => write!(f, "create {} desktops", count),
+            LayoutChange::Swap(a, b) => write!(f, "swap desktops {} and {}", a + 1, b + 1),
+            LayoutChange::Rename { desktop_idx, from, to } if from.is_empty() => {
+                write!(f, "name desktop {} '{}'", desktop_idx + 1, to)
+            }
+            LayoutChange::Rename { desktop_idx, from, to } => {
+                write!(f, "rename desktop {} from '{}' to '{}'", desktop_idx + 1, from, to)
+            }
+            LayoutChange::SetWallpaper { desktop_idx, path } => {
+                write!(f, "set the wallpaper of desktop {} to {}", desktop_idx + 1, path)
+            }
+            LayoutChange::Extra { desktop_idx, name } if name.is_empty() => {
+                write!(f, "desktop {} is not in the layout (left as is)", desktop_idx + 1)
+            }
+            LayoutChange::Extra { desktop_idx, name } => {
+                write!(f, "desktop {} '{}' is not in the layout (left as is)", desktop_idx + 1, name)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(name: &str, wallpaper: Option<&str>) -> DesktopSpec {
+        DesktopSpec { name: name.to_string(), wallpaper: wallpaper.map(str::to_string) }
+    }
+
+    fn desktop(name: &str, wallpaper: &str) -> ActualDesktop {
+        ActualDesktop { name: name.to_string(), wallpaper: wallpaper.to_string() }
+    }
+
+    #[test]
+    fn matching_desktops_need_no_changes() {
+        let specs = [spec("Mail", Some(r"C:\mail.jpg")), spec("", None)];
+        let actual = [desktop("mail", r"c:\MAIL.jpg"), desktop("Whatever", "")];
+        assert_eq!(plan(&specs, &actual), []);
+    }
+
+    #[test]
+    fn creates_missing_desktops_before_naming_them() {
+        let specs = [spec("Mail", None), spec("Code", None), spec("Chat", None)];
+        assert_eq!(
+            plan(&specs, &[desktop("Mail", "")]),
+            [
+                LayoutChange::Create(2),
+                LayoutChange::Rename { desktop_idx: 1, from: String::new(), to: "Code".to_string() },
+                LayoutChange::Rename { desktop_idx: 2, from: String::new(), to: "Chat".to_string() },
+            ]
+        );
+    }
+
+    #[test]
+    fn swaps_desktops_found_further_along() {
+        let specs = [spec("Mail", None), spec("Code", None), spec("Chat", None)];
+        let actual = [desktop("Chat", ""), desktop("Mail", ""), desktop("Code", "")];
+        // After the first swap the order is Mail, Chat, Code.
+        assert_eq!(plan(&specs, &actual), [LayoutChange::Swap(0, 1), LayoutChange::Swap(1, 2)]);
+    }
+
+    #[test]
+    fn renames_desktops_not_found_further_along() {
+        // "Mail" is only found before the desktop that should have it, which is in place already.
+        let specs = [spec("Mail", None), spec("Mail", None)];
+        let actual = [desktop("Mail", ""), desktop("Code", "")];
+        assert_eq!(
+            plan(&specs, &actual),
+            [LayoutChange::Rename { desktop_idx: 1, from: "Code".to_string(), to: "Mail".to_string() }]
+        );
+    }
+
+    #[test]
+    fn wallpapers_stay_with_the_position() {
+        let specs = [spec("Mail", Some("a.jpg")), spec("Code", Some("b.jpg"))];
+        let actual = [desktop("Code", "a.jpg"), desktop("Mail", "b.jpg")];
+        assert_eq!(plan(&specs, &actual), [LayoutChange::Swap(0, 1)]);
+
+        let specs = [spec("", Some("b.jpg"))];
+        assert_eq!(
+            plan(&specs, &[desktop("", "a.jpg")]),
+            [LayoutChange::SetWallpaper { desktop_idx: 0, path: "b.jpg".to_string() }]
+        );
+    }
+
+    #[test]
+    fn reports_extra_desktops_after_the_declared_ones() {
+        let specs = [spec("Code", None)];
+        let actual = [desktop("Mail", ""), desktop("Code", ""), desktop("", "")];
+        assert_eq!(
+            plan(&specs, &actual),
+            [
+                LayoutChange::Swap(0, 1),
+                LayoutChange::Extra { desktop_idx: 1, name: "Mail".to_string() },
+                LayoutChange::Extra { desktop_idx: 2, name: String::new() },
+            ]
+        );
+    }
+}
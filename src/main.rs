@@ -3,78 +3,1151 @@
 // For release builds, hide the console window
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod apps;
+mod backend;
+mod cleanup;
+mod command;
+mod config;
+mod desktops;
+mod error;
+mod events;
+mod executor;
+mod foreground;
+mod fuzzy;
+mod gesture;
+mod hotkeys;
+mod icon;
+mod ipc;
+mod layout;
+mod menu;
+mod modal;
+mod new_windows;
+mod notify;
+mod rules;
+mod tray;
+mod window;
+
 use anyhow::{Context, Result}; 
-use global_hotkey::{
-    hotkey::{Code, HotKey, Modifiers},
-    GlobalHotKeyManager, GlobalHotKeyEvent, HotKeyState,
-};
+use global_hotkey::{hotkey::HotKey, GlobalHotKeyManager, GlobalHotKeyEvent, HotKeyState};
 use log::{debug, error, info, warn};
 use std::{
-    collections::HashMap, 
+    collections::{HashMap, HashSet},
+    io::{BufRead, Write},
     sync::{Arc, Mutex}, // Added Arc, Mutex for shared state
     thread, 
-    ffi::OsString, 
-    os::windows::ffi::OsStringExt
+    time::{Duration, Instant},
 };
 use tao::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoopBuilder, EventLoopProxy, EventLoop}, 
 };
-// Corrected tray_icon imports: Using MenuItem from tray_icon::menu
-use tray_icon::{
-    menu::{accelerator::Accelerator, Menu, MenuEvent, MenuItem, PredefinedMenuItem}, 
-    TrayIconBuilder, TrayIconEvent,
+use tray_icon::{menu::MenuEvent, TrayIconEvent};
+
+use winvd::{
+    create_desktop, get_desktop_count, remove_desktop, switch_desktop, move_window_to_desktop, get_desktop_by_window,
+    is_pinned_app, is_pinned_window, pin_app, pin_window, unpin_app, unpin_window, DesktopEvent, DesktopEventThread,
 };
-// Removed direct muda import
 
-use winvd::{create_desktop, get_desktop_count, switch_desktop, move_window_to_desktop, get_desktop_by_window}; 
+use crate::apps::AppSpec;
+use crate::backend::BackendHealth;
+use crate::cleanup::{Cleanup, DesktopCollector};
+
+use crate::command::{dispatch, Command, CommandHandler, CommandSource, DesktopTarget, Direction, PinChange};
+use crate::config::Config;
+use crate::desktops::{CreatePlan, CreatePolicy, Rearrangement};
+use crate::error::DeskError;
+use crate::executor::{Action, ActionFinished, ActionOutcome, DesktopBackend, Executor};
+use crate::gesture::{Gesture, GestureTracker};
+use crate::hotkeys::HotkeyRegistry;
+use crate::ipc::IpcRequest;
+use crate::layout::{ActualDesktop, DesktopSpec, LayoutChange};
+use crate::modal::{Keymap, ModeKey, ModeMachine, ModeStep};
+use crate::rules::{Placement, WindowRule};
+use crate::tray::Tray;
 
 use windows::Win32::{
-    Foundation::{HWND, LPARAM, BOOL, TRUE, FALSE},
+    Foundation::HWND,
     UI::WindowsAndMessaging::{
-        MessageBoxW, MB_ICONERROR, MB_ICONINFORMATION, MESSAGEBOX_STYLE, GetForegroundWindow,
+        MessageBoxW, MB_ICONERROR, MB_ICONINFORMATION, MB_ICONQUESTION, MB_SETFOREGROUND, MB_TOPMOST, MB_YESNO,
+        IDYES, MESSAGEBOX_STYLE, GetForegroundWindow,
         IsWindow, IsWindowVisible, GetWindowLongW, GWL_STYLE, BringWindowToTop, SetForegroundWindow, WS_CHILD,
-        EnumWindows, GetWindowTextW,
+        IsIconic, ShowWindow, SW_RESTORE, SW_SHOWNOACTIVATE,
     },
 };
-use windows::core::PCWSTR; 
+use windows::core::{GUID, PCWSTR};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 enum CustomEvent {
-    HotkeyTriggered(u32),
+    HotkeyEvent(u32, HotKeyState, Instant), // A hotkey was pressed or released at that time
+    GestureTimer, // Time to check for long presses and taps that won't become double taps
+    ModeTimer, // Time to check whether the active mode timed out
+    CurrentDesktopChanged { old: Option<GUID>, new: Option<GUID> }, // Windows switched to another desktop
+    CollectDesktops, // Time to remove desktops created on demand that were left empty
+    SwitchTimeout(u64), // No confirmation arrived for the switch with this sequence number
+    RetryHotkeys, // Time to try registering hotkeys that were taken again
+    DesktopsChanged, // A desktop was created, removed, renamed, reordered or switched to
+    IpcRequest(IpcRequest),
+    TrayIconEvent(TrayIconEvent),
+    MenuEvent(MenuEvent),
+    ActionFinished(ActionFinished),
+    ReconnectBackend, // Time to check whether the virtual desktop service is back
+    WindowShown(isize), // A top-level window with this handle appeared
 }
 
-enum HotkeyAction {
-    Switch(usize),      // Target desktop index for switching
-    MoveWindow(usize),  // Target desktop index for moving window
+impl From<ActionFinished> for CustomEvent {
+    fn from(finished: ActionFinished) -> Self {
+        CustomEvent::ActionFinished(finished)
+    }
 }
 
 // Type alias for our shared map of last active windows
 type LastActiveWindowMap = Arc<Mutex<HashMap<u32, HWND>>>;
+// Desktops created on demand, shared by the worker that creates them and the event loop
+// that sees them being left.
+type SharedCollector = Arc<Mutex<DesktopCollector<GUID>>>;
+
+// What the worker needs to create desktops on demand and clean them up again.
+struct OnDemandDesktops {
+    policy: CreatePolicy,
+    cleanup: Cleanup,
+    collector: SharedCollector,
+}
+
+// Carries out actions for the executor with the virtual desktop service.
+struct DesktopService {
+    // The last active window on each desktop. Lives on the worker, which does all
+    // switching and focusing.
+    last_active_windows: LastActiveWindowMap,
+    on_demand: OnDemandDesktops,
+}
+
+impl DesktopBackend for DesktopService {
+    fn run(&mut self, action: Action) -> Result<ActionOutcome, DeskError> {
+        run_action(action, &self.last_active_windows, &self.on_demand)
+    }
+}
 
-const TRAY_ICON_TOOLTIP: &str = "DeskHopper";
-const MENU_ID_ABOUT_STR: &str = "about"; 
-const MENU_ID_EXIT_STR: &str = "exit";   
 const APP_NAME: &str = "DeskHopper";
 
-const ICON_BYTES: &[u8] = include_bytes!("../icon.ico");
+// Backoff between attempts to reach the virtual desktop service after it went away.
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_millis(500);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+// How often hotkeys taken by other applications are tried again.
+const HOTKEY_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+// Placed windows are remembered so showing one again doesn't move it back. Past this many,
+// the ones that were closed are forgotten.
+const PLACED_WINDOWS_PRUNE_AT: usize = 256;
+
+/// A virtual desktop as shown to the user: its 0-based index and its name, if it has one.
+#[derive(Clone)]
+pub struct DesktopInfo {
+    pub index: usize,
+    pub name: Option<String>,
+}
+
+impl DesktopInfo {
+    pub fn display_name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!("Desktop {}", self.index + 1),
+        }
+    }
+}
+
+/// Lists all virtual desktops in order, along with the index of the current one.
+fn query_desktops() -> Result<(Vec<DesktopInfo>, Option<usize>)> {
+    let desktops = winvd::get_desktops()
+        .map_err(|e| anyhow::anyhow!("Failed to list virtual desktops: {:?}", e))?;
+    let mut infos = Vec::with_capacity(desktops.len());
+    for (index, desktop) in desktops.iter().enumerate() {
+        // Unnamed desktops report an empty name; fall back to the default label for those.
+        let name = desktop.get_name().ok().filter(|name| !name.is_empty());
+        infos.push(DesktopInfo { index, name });
+    }
+    let current_idx = winvd::get_current_desktop()
+        .and_then(|desktop| desktop.get_index())
+        .ok()
+        .map(|idx| idx as usize);
+    Ok((infos, current_idx))
+}
+
+fn refresh_tray_desktops(tray: &mut Tray) {
+    match query_desktops() {
+        Ok((desktops, current_idx)) => {
+            if let Err(e) = tray.set_desktops(&desktops, current_idx) {
+                warn!("Failed to update desktop list in tray menu: {:?}", e);
+            }
+        }
+        Err(e) => warn!("Could not refresh desktop list for tray menu: {:?}", e),
+    }
+}
+
+struct PendingFocus {
+    seq: u64,
+    desktop_idx: usize,
+}
+
+/// State owned by the event loop, which carries out commands from every input source.
+struct App {
+    tray: Tray,
+    hotkeys: HotkeyRegistry,
+    gestures: GestureTracker,
+    modes: ModeMachine,
+    // The hotkey ID of each key the mode reacts to, and the hotkey grabbed for it.
+    mode_keys: HashMap<u32, (ModeKey, HotKey)>,
+    executor: Executor,
+    // Desktop switches handed to the executor that haven't finished yet.
+    switches_in_flight: usize,
+    // Reply channel of the IPC request being dispatched. An action queued for it takes the
+    // channel along, so the reply is sent once the action is done.
+    pending_ipc_reply: Option<std::sync::mpsc::Sender<String>>,
+    exit_requested: bool,
+    backend: BackendHealth,
+    // A switch waiting for Windows to confirm it before a window is focused.
+    pending_focus: Option<PendingFocus>,
+    // The desktop Windows last reported switching to. The report can arrive before the
+    // worker says the switch is done.
+    confirmed_desktop: Option<GUID>,
+    last_switch_seq: u64,
+    switch_confirm_timeout: Duration,
+    // Registered with the running explorer instance, so replaced after it restarts.
+    desktop_event_thread: Option<DesktopEventThread>,
+    cleanup: Cleanup,
+    collector: SharedCollector,
+    rules: Vec<WindowRule>,
+    apps: Vec<AppSpec>,
+    // Windows a rule already moved.
+    placed_windows: HashSet<isize>,
+    proxy: EventLoopProxy<CustomEvent>,
+}
+
+impl App {
+    /// Reports a failed command to the user, except for failures caused by a known outage
+    /// of the virtual desktop service.
+    fn report_failure(&mut self, e: &DeskError) {
+        if self.track_outage(e) {
+            notify::report_error(e.title(), &e.to_string());
+        }
+    }
+
+    /// Starts recovery if `e` means the virtual desktop service went away. Returns whether
+    /// `e` still needs reporting: failures during an outage are covered by the one report
+    /// made when it began.
+    fn track_outage(&mut self, e: &DeskError) -> bool {
+        if !e.is_retryable() {
+            return true;
+        }
+        if self.backend.mark_disconnected(Instant::now()) {
+            warn!("Lost the virtual desktop service: {}", e);
+            notify::report_error(e.title(), &format!("{}\nDeskHopper will reconnect once it is back.", e));
+            self.schedule_reconnect();
+        } else {
+            debug!("Virtual desktop service still unavailable; not reporting: {}", e);
+        }
+        false
+    }
+
+    fn hotkey_event(&mut self, id: u32, state: HotKeyState, at: Instant) {
+        let Some(actions) = self.hotkeys.actions_for(id) else {
+            match self.mode_keys.get(&id) {
+                Some(&(key, _)) if state == HotKeyState::Pressed => self.mode_key(key, at),
+                Some(_) => (),
+                None => warn!("Received unknown hotkey ID via UserEvent: {}", id),
+            }
+            return;
+        };
+        for (due_id, gesture) in self.gestures.poll(at) {
+            self.run_gesture(due_id, gesture);
+        }
+        let gesture = match state {
+            HotKeyState::Pressed => self.gestures.pressed(id, at, actions.gestures()),
+            HotKeyState::Released => self.gestures.released(id, at),
+        };
+        if let Some(gesture) = gesture {
+            self.run_gesture(id, gesture);
+        }
+        self.schedule_gesture_timer();
+    }
+
+    fn run_gesture(&mut self, id: u32, gesture: Gesture) {
+        let Some(command) = self.hotkeys.actions_for(id).and_then(|actions| actions.command(gesture)) else {
+            debug!("No action for {:?} of hotkey ID {}", gesture, id);
+            return;
+        };
+        info!("Hotkey ID {} {:?}", id, gesture);
+        self.run_hotkey_command(command);
+    }
+
+    fn run_hotkey_command(&mut self, command: Command) {
+        match dispatch(command, CommandSource::Hotkey, self) {
+            Ok(Some(output)) => show_output_dialog(output),
+            Ok(None) => (),
+            Err(e) => self.report_failure(&e),
+        }
+    }
+
+    fn mode_key(&mut self, key: ModeKey, at: Instant) {
+        match self.modes.key(key, at) {
+            ModeStep::Run(command) => {
+                info!("Mode key {:?} -> {}", key, command);
+                self.run_hotkey_command(command);
+            }
+            ModeStep::Pending => debug!("Mode key {:?} starts a sequence.", key),
+            ModeStep::Unbound => debug!("Mode key {:?} is not bound; sequence discarded.", key),
+            ModeStep::Exited => self.left_mode(),
+            // Released after leaving the mode but before the key was let go of.
+            ModeStep::Inactive => debug!("Ignoring mode key {:?}; no mode is active.", key),
+        }
+        self.schedule_mode_timer();
+    }
+
+    // Releases the mode's keys and updates the tray once the mode was left.
+    fn left_mode(&mut self) {
+        self.hotkeys.release_grabbed();
+        info!("Left {} mode.", self.modes.keymap().name);
+        if let Err(e) = self.tray.set_mode(None) {
+            warn!("Failed to update tray for mode: {:?}", e);
+        }
+    }
+
+    // Wakes the event loop when the active mode times out.
+    fn schedule_mode_timer(&self) {
+        let Some(deadline) = self.modes.deadline() else { return };
+        events::send_after(&self.proxy, deadline.saturating_duration_since(Instant::now()), CustomEvent::ModeTimer);
+    }
+
+    // Wakes the event loop when the next gesture is due, if one is waiting on time.
+    fn schedule_gesture_timer(&self) {
+        let Some(deadline) = self.gestures.next_deadline() else { return };
+        events::send_after(&self.proxy, deadline.saturating_duration_since(Instant::now()), CustomEvent::GestureTimer);
+    }
+
+    /// Focuses a window on `desktop_idx` once Windows reports having switched to it, right
+    /// away if it already has, or after the confirmation timeout. Supersedes any switch
+    /// still waiting.
+    fn focus_after_switch(&mut self, desktop_idx: usize) {
+        let target = winvd::get_desktop(desktop_idx as u32).get_id().ok();
+        if target.is_some() && target == self.confirmed_desktop {
+            info!("Switch to {} already confirmed.", describe_desktop(desktop_idx));
+            self.pending_focus = None;
+            self.restore_focus();
+            return;
+        }
+        self.last_switch_seq += 1;
+        let seq = self.last_switch_seq;
+        self.pending_focus = Some(PendingFocus { seq, desktop_idx });
+
+        events::send_after(&self.proxy, self.switch_confirm_timeout, CustomEvent::SwitchTimeout(seq));
+    }
+
+    fn switch_confirmed(&mut self) {
+        let Some(pending) = &self.pending_focus else { return };
+        let current_idx = winvd::get_current_desktop().and_then(|desktop| desktop.get_index());
+        match current_idx {
+            Ok(idx) if idx as usize == pending.desktop_idx => {
+                info!("Switch to {} confirmed.", describe_desktop(idx as usize));
+                self.pending_focus = None;
+                self.restore_focus();
+            }
+            // Another switch completed, e.g. one made with Windows' own shortcuts; keep waiting.
+            Ok(idx) => debug!(
+                "Now on {} while waiting for {}.",
+                describe_desktop(idx as usize),
+                describe_desktop(pending.desktop_idx)
+            ),
+            Err(e) => warn!("Could not determine current desktop after switch: {:?}", e),
+        }
+    }
+
+    fn switch_timed_out(&mut self, seq: u64) {
+        if !matches!(&self.pending_focus, Some(pending) if pending.seq == seq) {
+            return;
+        }
+        self.pending_focus = None;
+        warn!("Desktop switch not confirmed within {:?}; focusing a window anyway.", self.switch_confirm_timeout);
+        self.restore_focus();
+    }
+
+    fn restore_focus(&mut self) {
+        // The desktop is about to change again; focus is restored once the last switch lands.
+        if self.switches_in_flight > 0 {
+            debug!("Not restoring focus; {} switch(es) still queued.", self.switches_in_flight);
+            return;
+        }
+        self.executor.submit(Action::FocusCurrentDesktop, None);
+    }
+
+    fn action_finished(&mut self, finished: ActionFinished) {
+        if matches!(
+            finished.action,
+            Action::SwitchDesktop(_) | Action::MoveWindowAndFollow(..) | Action::FocusWindow(_) | Action::FocusOrLaunch(_)
+        ) {
+            self.switches_in_flight = self.switches_in_flight.saturating_sub(1);
+        }
+        match &finished.result {
+            Ok(ActionOutcome::Switched { desktop_idx, was_current }) => {
+                if *was_current {
+                    // Windows reports no change when switching to the current desktop.
+                    self.pending_focus = None;
+                    self.restore_focus();
+                } else {
+                    self.focus_after_switch(*desktop_idx);
+                }
+            }
+            // Command line clients print it themselves.
+            Ok(ActionOutcome::Output(output)) if finished.reply_to.is_none() => show_output_dialog(output.clone()),
+            Ok(_) => (),
+            // The user said no; there's nothing to tell them.
+            Err(DeskError::CreationDeclined(_)) => (),
+            // Cleanup happens in the background and is tried again on the next switch.
+            Err(e) if finished.action == Action::CollectDesktops => {
+                if self.track_outage(e) {
+                    warn!("Failed to remove unused desktops: {}", e);
+                }
+            }
+            // Failures of IPC requests are the client's to report.
+            Err(e) if finished.reply_to.is_some() => {
+                self.track_outage(e);
+            }
+            Err(e) => self.report_failure(e),
+        }
+        if let Some(reply_to) = finished.reply_to {
+            let reply = match &finished.result {
+                Ok(ActionOutcome::Output(output)) => ipc::ok_reply(Some(output.clone())),
+                Ok(_) => ipc::ok_reply(None),
+                Err(e) => ipc::error_reply(e),
+            };
+            // The client may have given up waiting; nothing to do then.
+            let _ = reply_to.send(reply);
+        }
+    }
+
+    // Moves or pins a newly shown window as the first rule that matches it says.
+    fn window_shown(&mut self, raw_hwnd: isize) {
+        if self.placed_windows.contains(&raw_hwnd) {
+            return;
+        }
+        let hwnd = HWND(raw_hwnd as *mut _);
+        let properties = window::properties(hwnd);
+        let Some(rule) = rules::find_rule(&self.rules, &properties) else { return };
+        info!(
+            "Window {:?} ({} '{}', class {}) matches a rule: {:?}.",
+            hwnd, properties.exe, properties.title, properties.class, rule.placement
+        );
+        let command = match &rule.placement {
+            Placement::Desktop { desktop, follow: true } => Command::MoveWindowAndFollow(raw_hwnd, desktop.clone()),
+            Placement::Desktop { desktop, follow: false } => Command::MoveWindow(raw_hwnd, desktop.clone()),
+            Placement::Pinned => Command::PinWindow(raw_hwnd, PinChange::Pin),
+        };
+        // Windows that close right after showing, like splash screens, aren't worth a notification.
+        if let Err(e) = dispatch(command, CommandSource::Rule, self) {
+            warn!("Could not place window {:?} by its rule: {}", hwnd, e);
+        }
+
+        if self.placed_windows.len() >= PLACED_WINDOWS_PRUNE_AT {
+            self.placed_windows.retain(|&placed| unsafe { IsWindow(HWND(placed as *mut _)) }.as_bool());
+        }
+        self.placed_windows.insert(raw_hwnd);
+    }
+
+    fn submit_move(&mut self, hwnd: HWND, target: DesktopTarget, follow: bool) {
+        let action = if follow {
+            self.switches_in_flight += 1;
+            Action::MoveWindowAndFollow(target, hwnd.0 as isize)
+        } else {
+            Action::MoveWindow(target, hwnd.0 as isize)
+        };
+        self.executor.submit(action, self.pending_ipc_reply.take());
+    }
+
+    // Starts the grace period of created desktops that aren't current, and schedules their removal.
+    fn desktop_left(&mut self, old: GUID, new: GUID) {
+        if self.cleanup == Cleanup::Off {
+            return;
+        }
+        let deadline = {
+            let mut collector = self.collector.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            collector.switched(old, new, Instant::now());
+            collector.next_deadline()
+        };
+        let Some(deadline) = deadline else { return };
+        events::send_after(&self.proxy, deadline.saturating_duration_since(Instant::now()), CustomEvent::CollectDesktops);
+    }
+
+    fn schedule_reconnect(&mut self) {
+        let delay = self.backend.next_retry_delay();
+        info!("Checking the virtual desktop service again in {:?}.", delay);
+        events::send_after(&self.proxy, delay, CustomEvent::ReconnectBackend);
+    }
+
+    fn reconnect_backend(&mut self) {
+        if self.backend.is_connected() {
+            return;
+        }
+        // winvd drops and recreates its COM objects when a call finds them disconnected,
+        // so a successful call means it is talking to the new explorer instance.
+        match get_desktop_count() {
+            Ok(_) => {
+                let outage = self.backend.mark_connected(Instant::now()).unwrap_or_default();
+                info!("Virtual desktop service is back after {:.1}s.", outage.as_secs_f32());
+                // Stop the old listener before registering a new one.
+                self.desktop_event_thread = None;
+                self.desktop_event_thread = start_desktop_event_listener(&self.proxy);
+                refresh_tray_desktops(&mut self.tray);
+                // Hotkeys belong to our own message window rather than to explorer, so
+                // they survive the restart and need no re-registration.
+            }
+            Err(e) => {
+                debug!("Virtual desktop service still unavailable: {:?}", e);
+                self.schedule_reconnect();
+            }
+        }
+    }
+}
+
+impl CommandHandler for App {
+    fn switch_desktop(&mut self, target: DesktopTarget) -> Result<(), DeskError> {
+        self.switches_in_flight += 1;
+        self.executor.submit(Action::SwitchDesktop(target), self.pending_ipc_reply.take());
+        Ok(())
+    }
+
+    fn move_active_window(&mut self, target: DesktopTarget, follow: bool) -> Result<(), DeskError> {
+        let hwnd = active_window()?;
+        self.submit_move(hwnd, target, follow);
+        Ok(())
+    }
+
+    fn pin_active_window(&mut self, change: PinChange, whole_app: bool) -> Result<(), DeskError> {
+        let hwnd = active_window()?;
+        self.executor.submit(
+            Action::PinWindow { hwnd: hwnd.0 as isize, change, whole_app },
+            self.pending_ipc_reply.take(),
+        );
+        Ok(())
+    }
+
+    fn move_window(&mut self, hwnd: isize, target: DesktopTarget, follow: bool) -> Result<(), DeskError> {
+        let hwnd = window_by_handle(hwnd)?;
+        self.submit_move(hwnd, target, follow);
+        Ok(())
+    }
+
+    fn pin_window(&mut self, hwnd: isize, change: PinChange) -> Result<(), DeskError> {
+        let hwnd = window_by_handle(hwnd)?;
+        self.executor.submit(
+            Action::PinWindow { hwnd: hwnd.0 as isize, change, whole_app: false },
+            self.pending_ipc_reply.take(),
+        );
+        Ok(())
+    }
+
+    fn list_windows(&mut self) -> Result<(), DeskError> {
+        self.executor.submit(Action::ListWindows, self.pending_ipc_reply.take());
+        Ok(())
+    }
+
+    fn focus_window(&mut self, query: String) -> Result<(), DeskError> {
+        self.switches_in_flight += 1;
+        self.executor.submit(Action::FocusWindow(query), self.pending_ipc_reply.take());
+        Ok(())
+    }
+
+    fn focus_or_launch(&mut self, app: String) -> Result<(), DeskError> {
+        let Some(spec) = apps::find_app(&self.apps, &app) else {
+            return Err(DeskError::UnknownApp(app));
+        };
+        self.switches_in_flight += 1;
+        self.executor.submit(Action::FocusOrLaunch(spec.clone()), self.pending_ipc_reply.take());
+        Ok(())
+    }
+
+    fn list_windows_elsewhere(&mut self) -> Result<(), DeskError> {
+        self.executor.submit(Action::ListWindowsElsewhere, self.pending_ipc_reply.take());
+        Ok(())
+    }
+
+    fn bring_window(&mut self, hwnd: isize) -> Result<(), DeskError> {
+        let hwnd = window_by_handle(hwnd)?;
+        self.executor.submit(Action::BringWindow(hwnd.0 as isize), self.pending_ipc_reply.take());
+        Ok(())
+    }
+
+    fn create_desktop(&mut self, position: DesktopTarget) -> Result<(), DeskError> {
+        self.executor.submit(Action::CreateDesktopAt(position), self.pending_ipc_reply.take());
+        Ok(())
+    }
+
+    fn remove_desktop(&mut self) -> Result<(), DeskError> {
+        self.executor.submit(Action::RemoveCurrentDesktop, self.pending_ipc_reply.take());
+        Ok(())
+    }
+
+    fn swap_desktops(&mut self, a: DesktopTarget, b: DesktopTarget) -> Result<(), DeskError> {
+        self.executor.submit(Action::SwapDesktops(a, b), self.pending_ipc_reply.take());
+        Ok(())
+    }
+
+    fn rename_desktop(&mut self, name: String) -> Result<(), DeskError> {
+        self.executor.submit(Action::RenameCurrentDesktop(name), self.pending_ipc_reply.take());
+        Ok(())
+    }
+
+    fn apply_layout(&mut self) -> Result<(), DeskError> {
+        let config = Config::load().map_err(|e| DeskError::Config(format!("{:#}", e)))?;
+        self.executor.submit(Action::ApplyLayout(config.desktops.layout), self.pending_ipc_reply.take());
+        Ok(())
+    }
+
+    fn move_desktop(&mut self, direction: Direction) -> Result<(), DeskError> {
+        self.executor.submit(Action::MoveCurrentDesktop(direction), self.pending_ipc_reply.take());
+        Ok(())
+    }
+
+    fn hotkeys_paused(&self) -> bool {
+        self.hotkeys.is_paused()
+    }
+
+    fn set_hotkeys_paused(&mut self, paused: bool) {
+        if paused == self.hotkeys.is_paused() {
+            info!("Hotkeys are already {}.", if paused { "paused" } else { "active" });
+        } else {
+            self.hotkeys.set_paused(paused);
+            self.tray.set_hotkey_failures(self.hotkeys.failed_count());
+        }
+        // Also keeps the menu check mark, which toggles on click, in sync.
+        if let Err(e) = self.tray.set_paused(paused) {
+            warn!("Failed to update tray for paused state: {:?}", e);
+        }
+    }
+
+    fn hotkey_report(&self) -> String {
+        self.hotkeys.report()
+    }
+
+    fn enter_mode(&mut self) {
+        if self.modes.enter(Instant::now()) {
+            let keys: Vec<HotKey> = self.mode_keys.values().map(|(_, hotkey)| *hotkey).collect();
+            let failed = self.hotkeys.grab(&keys);
+            if failed > 0 {
+                warn!("{} of {} mode keys are unavailable.", failed, keys.len());
+            }
+            let name = self.modes.keymap().name;
+            info!("Entered {} mode.", name);
+            if let Err(e) = self.tray.set_mode(Some(name)) {
+                warn!("Failed to update tray for mode: {:?}", e);
+            }
+        }
+        // Entering again restarts the timeout.
+        self.schedule_mode_timer();
+    }
+
+    fn exit_mode(&mut self) {
+        if self.modes.exit() {
+            self.left_mode();
+        }
+    }
+
+    fn current_mode(&self) -> Option<&str> {
+        self.modes.current()
+    }
+
+    fn show_about(&mut self) {
+        show_about_dialog();
+    }
+
+    fn exit(&mut self) {
+        info!("Exit requested. Shutting down.");
+        self.exit_requested = true;
+    }
+}
+
+// The window commands act on.
+fn active_window() -> Result<HWND, DeskError> {
+    // Prefer the tracked window: when the command comes from the tray, the taskbar has the foreground.
+    let hwnd = foreground::last_foreground_window().unwrap_or_else(|| unsafe { GetForegroundWindow() });
+    if !unsafe { IsWindow(hwnd) }.as_bool() {
+        warn!("Active window {:?} no longer exists; nothing to act on.", hwnd);
+        return Err(DeskError::NoActiveWindow);
+    }
+    Ok(hwnd)
+}
+
+// The window with the handle `hwnd`, as given in a command.
+fn window_by_handle(hwnd: isize) -> Result<HWND, DeskError> {
+    let window = HWND(hwnd as *mut _);
+    if !unsafe { IsWindow(window) }.as_bool() {
+        return Err(DeskError::WindowNotFound(format!("{:#x}", hwnd)));
+    }
+    Ok(window)
+}
+
+// Carries out an action on the executor's worker thread.
+fn run_action(action: Action, last_active_map: &LastActiveWindowMap, on_demand: &OnDemandDesktops) -> Result<ActionOutcome, DeskError> {
+    match action {
+        Action::SwitchDesktop(target) => {
+            let desktop_idx = resolve_desktop_target(target)?;
+            let was_current = winvd::get_current_desktop()
+                .and_then(|desktop| desktop.get_index())
+                .is_ok_and(|idx| idx as usize == desktop_idx);
+            handle_switch_to_desktop(desktop_idx, last_active_map, on_demand)?;
+            Ok(ActionOutcome::Switched { desktop_idx, was_current })
+        }
+        Action::MoveWindow(target, hwnd) => {
+            let desktop_idx = resolve_desktop_target(target)?;
+            handle_move_window_to_desktop(desktop_idx, HWND(hwnd as *mut _), on_demand)?;
+            Ok(ActionOutcome::Done)
+        }
+        Action::MoveWindowAndFollow(target, hwnd) => {
+            // Resolved once, so `New` moves and switches to the same new desktop.
+            let desktop_idx = resolve_desktop_target(target)?;
+            let hwnd = HWND(hwnd as *mut _);
+            handle_move_window_to_desktop(desktop_idx, hwnd, on_demand)?;
+            // Have the focus restore after the switch pick the moved window.
+            last_active_map
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .insert(desktop_idx as u32, hwnd);
+            handle_switch_to_desktop(desktop_idx, last_active_map, on_demand)?;
+            Ok(ActionOutcome::Switched { desktop_idx, was_current: false })
+        }
+        Action::FocusCurrentDesktop => {
+            focus_current_desktop(last_active_map);
+            Ok(ActionOutcome::Done)
+        }
+        Action::CollectDesktops => {
+            collect_desktops(on_demand)?;
+            Ok(ActionOutcome::Done)
+        }
+        Action::CreateDesktopAt(position) => {
+            let count = get_desktop_count()? as usize;
+            let position = resolve_desktop_target(position)?;
+            if position > count {
+                return Err(DeskError::DesktopNotFound(position));
+            }
+            let max_count = on_demand.policy.max_count;
+            if max_count > 0 && count >= max_count {
+                return Err(DeskError::DesktopLimit(max_count));
+            }
+            create_one_desktop()?;
+            info!("Created a desktop to insert at index {}.", position);
+            rearrange_desktops(Rearrangement::InsertAt(position), count)
+        }
+        Action::RemoveCurrentDesktop => remove_current_desktop(),
+        Action::SwapDesktops(a, b) => {
+            let (a, b) = (resolve_desktop_target(a)?, resolve_desktop_target(b)?);
+            let count = get_desktop_count()? as usize;
+            rearrange_desktops(Rearrangement::Swap(a, b), count)
+        }
+        Action::MoveCurrentDesktop(direction) => {
+            let count = get_desktop_count()? as usize;
+            let current_idx = winvd::get_current_desktop()?.get_index()? as usize;
+            rearrange_desktops(Rearrangement::Shift(current_idx, direction), count)
+        }
+        Action::RenameCurrentDesktop(name) => {
+            let desktop = winvd::get_current_desktop()?;
+            desktop.set_name(&name)?;
+            info!("Renamed {}.", describe_desktop(desktop.get_index()? as usize));
+            Ok(ActionOutcome::Done)
+        }
+        Action::ApplyLayout(specs) => apply_layout(&specs, on_demand),
+        Action::PinWindow { hwnd, change, whole_app } => {
+            set_pinned(HWND(hwnd as *mut _), change, whole_app)?;
+            Ok(ActionOutcome::Done)
+        }
+        Action::ListWindows => Ok(ActionOutcome::Output(window_lines(&desktop_windows().collect::<Vec<_>>()))),
+        Action::ListWindowsElsewhere => {
+            let current_idx = winvd::get_current_desktop()?.get_index()? as usize;
+            // Pinned windows are here already.
+            let windows: Vec<(HWND, WindowPlace)> = desktop_windows()
+                .filter(|(_, place)| matches!(place, WindowPlace::Desktop(idx) if *idx != current_idx))
+                .collect();
+            if windows.is_empty() {
+                return Ok(ActionOutcome::Output("There are no windows on other desktops.".to_string()));
+            }
+            Ok(ActionOutcome::Output(window_lines(&windows)))
+        }
+        Action::FocusWindow(query) => focus_window_matching(&query, last_active_map, on_demand),
+        Action::FocusOrLaunch(app) => focus_or_launch(&app, last_active_map, on_demand),
+        Action::BringWindow(hwnd) => {
+            let hwnd = HWND(hwnd as *mut _);
+            let desktop_idx = winvd::get_current_desktop()?.get_index()? as usize;
+            handle_move_window_to_desktop(desktop_idx, hwnd, on_demand)?;
+            last_active_map
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .insert(desktop_idx as u32, hwnd);
+            focus_window(hwnd);
+            Ok(ActionOutcome::Done)
+        }
+    }
+}
+
+// One line per window, for `windows` and `bring`: handle, desktop, executable and title,
+// separated by tabs.
+fn window_lines(windows: &[(HWND, WindowPlace)]) -> String {
+    windows
+        .iter()
+        .map(|(hwnd, place)| {
+            let properties = window::properties(*hwnd);
+            let desktop = match place {
+                WindowPlace::Desktop(desktop_idx) => describe_desktop(*desktop_idx),
+                WindowPlace::Everywhere => "all desktops".to_string(),
+            };
+            format!("{:#x}\t{}\t{}\t{}", hwnd.0 as isize, desktop, properties.exe, properties.title)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
 
-struct EnumCallbackData {
-    target_desktop_id: u32,
-    found_hwnd: Option<HWND>,
+// Focuses the window that best matches `query`, switching to its desktop first if needed.
+fn focus_window_matching(query: &str, last_active_map: &LastActiveWindowMap, on_demand: &OnDemandDesktops) -> Result<ActionOutcome, DeskError> {
+    let windows: Vec<(HWND, WindowPlace)> = desktop_windows().collect();
+    let descriptions: Vec<String> = windows
+        .iter()
+        .map(|(hwnd, _)| {
+            let properties = window::properties(*hwnd);
+            let app = properties.exe.strip_suffix(".exe").unwrap_or(&properties.exe).to_string();
+            format!("{} {}", app, properties.title)
+        })
+        .collect();
+    // A handle as listed by `windows` picks that very window. If it was closed since, no
+    // other window will do.
+    let best = match query.strip_prefix("0x").and_then(|hex| isize::from_str_radix(hex, 16).ok()) {
+        Some(raw) => windows.iter().position(|(hwnd, _)| hwnd.0 as isize == raw),
+        None => fuzzy::best_match(query, &descriptions),
+    };
+    let Some(best) = best else {
+        return Err(DeskError::WindowNotFound(query.to_string()));
+    };
+    let (hwnd, place) = windows[best];
+    info!("'{}' matches window {:?} ({}).", query, hwnd, descriptions[best]);
+    focus_listed_window(hwnd, place, last_active_map, on_demand)
 }
 
-fn load_tray_icon() -> Result<tray_icon::Icon> { 
-    let image = image::load_from_memory_with_format(ICON_BYTES, image::ImageFormat::Ico)
-        .context("Failed to load icon from memory")?
-        .to_rgba8();
-    let (width, height) = image.dimensions();
-    let icon_data = image.into_raw();
-    tray_icon::Icon::from_rgba(icon_data, width, height)
-        .context("Failed to create tray icon from RGBA data")
+// Focuses the most recently used window of the app, or starts the app, on its desktop if
+// it has one.
+fn focus_or_launch(app: &AppSpec, last_active_map: &LastActiveWindowMap, on_demand: &OnDemandDesktops) -> Result<ActionOutcome, DeskError> {
+    // Z order puts the most recently used window first.
+    if let Some((hwnd, place)) = desktop_windows().find(|(hwnd, _)| app.matcher.matches(&window::properties(*hwnd))) {
+        info!("Focusing window {:?} of app '{}'.", hwnd, app.name);
+        return focus_listed_window(hwnd, place, last_active_map, on_demand);
+    }
+
+    // New windows open on the current desktop, so go there first.
+    let mut outcome = ActionOutcome::Done;
+    if let Some(target) = app.desktop.clone() {
+        let desktop_idx = resolve_desktop_target(target)?;
+        let was_current = winvd::get_current_desktop()?.get_index()? as usize == desktop_idx;
+        if !was_current {
+            handle_switch_to_desktop(desktop_idx, last_active_map, on_demand)?;
+            outcome = ActionOutcome::Switched { desktop_idx, was_current };
+        }
+    }
+    info!("App '{}' has no window; starting: {}", app.name, app.command);
+    apps::launch(app).map_err(|e| DeskError::LaunchFailed(format!("{}: {}", app.command, e)))?;
+    Ok(outcome)
+}
+
+// Focuses a window found by `desktop_windows`, switching to its desktop first if it's
+// on another one.
+fn focus_listed_window(hwnd: HWND, place: WindowPlace, last_active_map: &LastActiveWindowMap, on_demand: &OnDemandDesktops) -> Result<ActionOutcome, DeskError> {
+    let current_idx = winvd::get_current_desktop()?.get_index()? as usize;
+    match place {
+        WindowPlace::Desktop(desktop_idx) if desktop_idx != current_idx => {
+            // Un-minimize it where it is; activating it would have Windows switch desktops
+            // on its own.
+            if unsafe { IsIconic(hwnd) }.as_bool() {
+                let _ = unsafe { ShowWindow(hwnd, SW_SHOWNOACTIVATE) };
+            }
+            // Have the focus restore after the switch pick this window.
+            last_active_map
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .insert(desktop_idx as u32, hwnd);
+            handle_switch_to_desktop(desktop_idx, last_active_map, on_demand)?;
+            Ok(ActionOutcome::Switched { desktop_idx, was_current: false })
+        }
+        _ => {
+            focus_window(hwnd);
+            Ok(ActionOutcome::Done)
+        }
+    }
+}
+
+// Restores the window if it is minimized and gives it the foreground.
+fn focus_window(hwnd: HWND) {
+    if unsafe { IsIconic(hwnd) }.as_bool() {
+        let _ = unsafe { ShowWindow(hwnd, SW_RESTORE) };
+    }
+    let _ = unsafe { BringWindowToTop(hwnd) };
+    if !unsafe { SetForegroundWindow(hwnd) }.as_bool() {
+        warn!("Could not give window {:?} the foreground.", hwnd);
+    }
+}
+
+// Pins or unpins a window, or every window of its application.
+fn set_pinned(hwnd: HWND, change: PinChange, whole_app: bool) -> Result<(), DeskError> {
+    let for_window = |e| DeskError::for_window(hwnd.0 as isize, e);
+    let pinned = if whole_app { is_pinned_app(hwnd) } else { is_pinned_window(hwnd) }.map_err(for_window)?;
+    let pin = match change {
+        PinChange::Pin => true,
+        PinChange::Unpin => false,
+        PinChange::Toggle => !pinned,
+    };
+    let what = if whole_app { "application of window" } else { "window" };
+    if pin == pinned {
+        info!("The {} {:?} is already {}.", what, hwnd, if pinned { "pinned" } else { "unpinned" });
+        return Ok(());
+    }
+    match (pin, whole_app) {
+        (true, false) => pin_window(hwnd),
+        (false, false) => unpin_window(hwnd),
+        (true, true) => pin_app(hwnd),
+        (false, true) => unpin_app(hwnd),
+    }
+    .map_err(for_window)?;
+    info!("{} the {} {:?}.", if pin { "Pinned" } else { "Unpinned" }, what, hwnd);
+    Ok(())
+}
+
+// The name and wallpaper of every desktop, in order.
+fn actual_desktops() -> Result<Vec<ActualDesktop>, DeskError> {
+    Ok(winvd::get_desktops()?
+        .iter()
+        .map(|desktop| ActualDesktop {
+            name: desktop.get_name().unwrap_or_default(),
+            // Not every version of Windows has per-desktop wallpapers.
+            wallpaper: desktop.get_wallpaper().unwrap_or_default(),
+        })
+        .collect())
+}
+
+// Makes the desktops match the declared layout. Desktops beyond it are only reported.
+fn apply_layout(specs: &[DesktopSpec], on_demand: &OnDemandDesktops) -> Result<ActionOutcome, DeskError> {
+    if specs.is_empty() {
+        return Ok(ActionOutcome::Done);
+    }
+    let changes = layout::plan(specs, &actual_desktops()?);
+    if changes.iter().all(|change| matches!(change, LayoutChange::Extra { .. })) {
+        info!("Desktops already match the layout.");
+    }
+    let mut outcome = ActionOutcome::Done;
+    for change in changes {
+        match &change {
+            LayoutChange::Create(count) => {
+                let max_count = on_demand.policy.max_count;
+                if max_count > 0 && get_desktop_count()? as usize + count > max_count {
+                    return Err(DeskError::DesktopLimit(max_count));
+                }
+                for _ in 0..*count {
+                    create_one_desktop()?;
+                }
+            }
+            LayoutChange::Swap(a, b) => {
+                let count = get_desktop_count()? as usize;
+                // Following the current desktop's windows may switch; focus the last one.
+                if let switched @ ActionOutcome::Switched { .. } = rearrange_desktops(Rearrangement::Swap(*a, *b), count)? {
+                    outcome = switched;
+                }
+            }
+            LayoutChange::Rename { desktop_idx, to, .. } => {
+                winvd::get_desktop(*desktop_idx as u32).set_name(to)?;
+            }
+            LayoutChange::SetWallpaper { desktop_idx, path } => {
+                if let Err(e) = winvd::get_desktop(*desktop_idx as u32).set_wallpaper(path) {
+                    warn!("Could not set the wallpaper of desktop {}: {:?}", desktop_idx + 1, e);
+                    continue;
+                }
+            }
+            LayoutChange::Extra { .. } => {
+                warn!("Layout: {}", change);
+                continue;
+            }
+        }
+        info!("Layout: {}", change);
+    }
+    Ok(outcome)
+}
+
+// Prints the changes `apply-layout` would make, without making them. Runs in the command
+// line client, so it works whether DeskHopper is running or not.
+fn print_layout_plan() -> Result<()> {
+    ipc::attach_parent_console();
+    let config = Config::load()?;
+    if config.desktops.layout.is_empty() {
+        println!("No layout is declared in the config file.");
+        return Ok(());
+    }
+    let actual = actual_desktops().map_err(|e| anyhow::anyhow!("Failed to read the current desktops: {}", e))?;
+    let changes = layout::plan(&config.desktops.layout, &actual);
+    if changes.is_empty() {
+        println!("Desktops already match the layout.");
+    }
+    for change in changes {
+        println!("{}", change);
+    }
+    Ok(())
+}
+
+// `deskhopper menu`: prints desktops and windows for a launcher, then reads the picked line
+// from stdin and sends its command to the running instance. `--list` and `--run` do only
+// one half, for launchers that sit in a pipeline between the two.
+fn run_menu(args: &[String]) -> Result<()> {
+    ipc::attach_parent_console();
+    let (list, run) = match args {
+        [] => (true, true),
+        [flag] if flag == "--list" => (true, false),
+        [flag] if flag == "--run" => (false, true),
+        _ => {
+            eprintln!("Usage: deskhopper menu [--list|--run]");
+            std::process::exit(1);
+        }
+    };
+
+    if list {
+        let (desktops, current_idx) = query_desktops()?;
+        let windows: Vec<menu::MenuWindow> = desktop_windows()
+            .map(|(hwnd, place)| {
+                let properties = window::properties(hwnd);
+                menu::MenuWindow {
+                    hwnd: hwnd.0 as isize,
+                    place: match place {
+                        WindowPlace::Desktop(desktop_idx) => describe_desktop(desktop_idx),
+                        WindowPlace::Everywhere => "all desktops".to_string(),
+                    },
+                    exe: properties.exe,
+                    title: properties.title,
+                }
+            })
+            .collect();
+        let mut stdout = std::io::stdout().lock();
+        for entry in menu::entries(&desktops, current_idx, &windows) {
+            writeln!(stdout, "{}", entry)?;
+        }
+        stdout.flush()?;
+    }
+
+    if run {
+        let mut line = String::new();
+        std::io::stdin().lock().read_line(&mut line)?;
+        // The launcher was dismissed.
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        match menu::parse_selection(&line) {
+            Ok(command) => ipc::send_and_print(&command.to_string())?,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Moves the windows and names of the first `count` desktops to where the rearrangement
+// puts them, then follows the current desktop's windows if they moved. Windows that can't
+// be moved stay behind.
+fn rearrange_desktops(rearrangement: Rearrangement, count: usize) -> Result<ActionOutcome, DeskError> {
+    let destinations = desktops::rearrange(rearrangement, count)?;
+    let desktops = winvd::get_desktops()?;
+    let current_idx = winvd::get_current_desktop()?.get_index()? as usize;
+
+    // Everything is looked up before anything moves, as moving changes the answers.
+    let mut new_names = vec![String::new(); desktops.len()];
+    for (from, to) in destinations.iter().enumerate() {
+        new_names[*to] = desktops[from].get_name().unwrap_or_default();
+    }
+    let windows: Vec<(HWND, usize)> = window::top_level_windows()
+        .into_iter()
+        // Pinned windows are on every desktop already.
+        .filter(|hwnd| !window::is_pinned(*hwnd))
+        .filter_map(|hwnd| Some((hwnd, get_desktop_by_window(hwnd).ok()?.get_index().ok()? as usize)))
+        .filter(|(_, from)| destinations.get(*from).is_some_and(|to| to != from))
+        .collect();
+
+    info!("Rearranging desktops ({:?}); moving {} window(s).", rearrangement, windows.len());
+    let mut failed = 0;
+    for (hwnd, from) in windows {
+        if let Err(e) = move_window_to_desktop(destinations[from] as u32, &hwnd) {
+            debug!("Could not move window {:?} from {}: {:?}", hwnd, describe_desktop(from), e);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        warn!("{} window(s) could not be moved and stayed on their desktop.", failed);
+    }
+    for (desktop, name) in desktops.iter().zip(&new_names) {
+        if desktop.get_name().ok().as_ref() != Some(name) {
+            if let Err(e) = desktop.set_name(name) {
+                warn!("Could not rename desktop {:?}: {:?}", desktop, e);
+            }
+        }
+    }
+
+    let new_idx = destinations.get(current_idx).copied().unwrap_or(current_idx);
+    if new_idx == current_idx {
+        return Ok(ActionOutcome::Done);
+    }
+    switch_desktop(new_idx as u32).map_err(|e| DeskError::for_desktop(new_idx, e))?;
+    Ok(ActionOutcome::Switched { desktop_idx: new_idx, was_current: false })
+}
+
+// Removes the current desktop. Windows moves its windows to the fallback desktop, the one
+// to the left if there is one, and switches there.
+fn remove_current_desktop() -> Result<ActionOutcome, DeskError> {
+    let desktops = winvd::get_desktops()?;
+    if desktops.len() <= 1 {
+        return Err(DeskError::LastDesktop);
+    }
+    let current_idx = winvd::get_current_desktop()?.get_index()? as usize;
+    let fallback_idx = if current_idx > 0 { current_idx - 1 } else { 1 };
+    let removed = describe_desktop(current_idx);
+    remove_desktop(desktops[current_idx], desktops[fallback_idx])?;
+    info!("Removed {}; its windows went to {}.", removed, describe_desktop(fallback_idx));
+    // Desktops after the removed one move down a place.
+    let desktop_idx = if fallback_idx > current_idx { fallback_idx - 1 } else { fallback_idx };
+    Ok(ActionOutcome::Switched { desktop_idx, was_current: false })
+}
+
+// Turns a target into a 0-based index; `New` is one past the last desktop, which the
+// switch and move handlers create if the policy allows.
+fn resolve_desktop_target(target: DesktopTarget) -> Result<usize, DeskError> {
+    match target {
+        DesktopTarget::Index(idx) => Ok(idx),
+        DesktopTarget::New => Ok(get_desktop_count()? as usize),
+        DesktopTarget::Name(name) => {
+            let names: Vec<String> = winvd::get_desktops()?
+                .iter()
+                .map(|desktop| desktop.get_name().unwrap_or_default())
+                .collect();
+            desktops::find_by_name(&names, &name).ok_or(DeskError::UnknownDesktopName(name))
+        }
+    }
+}
+
+// Names a desktop for the log, e.g. "desktop 2 'Mail'", or "desktop 2" if it has no name.
+fn describe_desktop(desktop_idx: usize) -> String {
+    match winvd::get_desktop(desktop_idx as u32).get_name() {
+        Ok(name) if !name.is_empty() => format!("desktop {} '{}'", desktop_idx + 1, name),
+        _ => format!("desktop {}", desktop_idx + 1),
+    }
 }
 
 fn main() -> Result<()> {
+    // Any arguments make this a command line client of the running instance.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args == ["apply-layout", "--dry-run"] {
+        return print_layout_plan();
+    }
+    if args.first().is_some_and(|arg| arg == "menu") {
+        return run_menu(&args[1..]);
+    }
+    if !args.is_empty() {
+        return ipc::run_client(&args);
+    }
+
     env_logger::Builder::from_default_env()
         .filter_level(log::LevelFilter::Info)
         .init();
@@ -86,31 +1159,63 @@ fn main() -> Result<()> {
         .build();
     let proxy: EventLoopProxy<CustomEvent> = event_loop.create_proxy();
 
-    let mut hotkey_manager = GlobalHotKeyManager::new().context("Failed to initialize GlobalHotKeyManager")?;
+    // Have tray and menu events wake the event loop as they happen, in order, instead of
+    // sitting in their channels until something else does. Installed before the tray
+    // exists so no early event is missed.
+    TrayIconEvent::set_event_handler(Some(events::forwarder(proxy.clone(), CustomEvent::TrayIconEvent, "tray")));
+    MenuEvent::set_event_handler(Some(events::forwarder(proxy.clone(), CustomEvent::MenuEvent, "menu")));
 
-    // Initialize the map for storing the last active window on each desktop
-    let last_active_windows_map: LastActiveWindowMap = Arc::new(Mutex::new(HashMap::new()));
-    
-    let mut hotkey_actions: HashMap<u32, HotkeyAction> = HashMap::new();
-    let mut registered_hotkey_structs: Vec<HotKey> = Vec::new();
+    let hotkey_manager = GlobalHotKeyManager::new().context("Failed to initialize GlobalHotKeyManager")?;
 
-    let _tray_icon = match setup_tray_icon() {
-        Ok(icon) => icon,
+    let (config, config_error) = match Config::load() {
+        Ok(config) => (config, None),
+        Err(e) => (Config::default(), Some(e)),
+    };
+    notify::init(&config.notifications);
+    if let Some(e) = config_error {
+        notify::report_error("Configuration Error", &format!("{:?}\nDefault settings will be used.", e));
+    }
+
+    let mut tray = match Tray::new(&config.tray_icon) {
+        Ok(tray) => tray,
         Err(e) => {
             let err_msg = format!("Failed to create system tray icon: {:?}\nApplication will exit.", e);
             error!("{}", err_msg);
+            // We're about to exit, so there's no event loop to block and a notification might not make it out.
             show_message_box("Error", &err_msg, MB_ICONERROR);
             return Err(e); 
         }
     };
     info!("System tray icon created.");
+    refresh_tray_desktops(&mut tray);
 
-    if let Err(e) = register_hotkeys(&mut hotkey_manager, &mut hotkey_actions, &mut registered_hotkey_structs) {
-        let err_msg = format!("Failed to register one or more hotkeys: {:?}\nSome hotkeys may not work.", e);
-        error!("{}", err_msg);
-        show_message_box("Hotkey Registration Error", &err_msg, MB_ICONERROR);
+    if let Err(e) = foreground::start_tracking() {
+        warn!("Failed to track foreground window changes: {:?}. The tray's move submenu may target the wrong window.", e);
+    }
+    if !config.rules.is_empty() {
+        match new_windows::start_watching(proxy.clone()) {
+            Ok(()) => info!("Placing new windows by {} window rule(s).", config.rules.len()),
+            Err(e) => warn!("Failed to watch for new windows: {:?}. Window rules won't apply.", e),
+        }
     }
 
+    let mut hotkeys = HotkeyRegistry::new(hotkey_manager);
+    hotkeys::register_configured_bindings(&mut hotkeys, &config.hotkeys.bindings);
+    hotkeys::register_default_bindings(&mut hotkeys, &config.hotkeys.pause_toggle);
+    hotkeys::register_leader(&mut hotkeys, &config.hotkeys.leader);
+    if let Some(summary) = hotkeys.failure_summary() {
+        notify::report_error("Hotkey Registration Error", &summary);
+    }
+    tray.set_hotkey_failures(hotkeys.failed_count());
+
+    let hotkey_retry_proxy = proxy.clone();
+    thread::spawn(move || loop {
+        thread::sleep(HOTKEY_RETRY_INTERVAL);
+        if hotkey_retry_proxy.send_event(CustomEvent::RetryHotkeys).is_err() {
+            break; // Main loop exited
+        }
+    });
+
     let hotkey_event_proxy = proxy.clone();
     thread::spawn(move || {
         let receiver = GlobalHotKeyEvent::receiver();
@@ -119,11 +1224,11 @@ fn main() -> Result<()> {
             match receiver.recv() {
                 Ok(event) => {
                     debug!("GlobalHotKeyEvent received: {:?}", event);
-                    if event.state == HotKeyState::Pressed {
-                        if hotkey_event_proxy.send_event(CustomEvent::HotkeyTriggered(event.id)).is_err() {
-                            error!("Failed to send hotkey event to main loop. Main loop likely exited.");
-                            break; 
-                        }
+                    // Timed here, as the event loop may be busy when it arrives.
+                    let custom_event = CustomEvent::HotkeyEvent(event.id, event.state, Instant::now());
+                    if hotkey_event_proxy.send_event(custom_event).is_err() {
+                        error!("Failed to send hotkey event to main loop. Main loop likely exited.");
+                        break; 
                     }
                 }
                 Err(e) => {
@@ -135,59 +1240,69 @@ fn main() -> Result<()> {
         info!("Hotkey listener thread finished.");
     });
 
-    info!("Event loop starting. Application is running in the background.");
+    let desktop_event_thread = start_desktop_event_listener(&proxy);
 
-    // Clone Arc for the event loop closure
-    let last_active_windows_map_for_loop = Arc::clone(&last_active_windows_map);
+    ipc::spawn_server(proxy.clone());
 
-    event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Wait;
+    let collector: SharedCollector = Arc::new(Mutex::new(DesktopCollector::new(Duration::from_secs(
+        config.desktops.cleanup_grace_seconds,
+    ))));
+    let on_demand = OnDemandDesktops {
+        policy: config.desktops.create_policy(),
+        cleanup: config.desktops.cleanup,
+        collector: collector.clone(),
+    };
+    let executor = Executor::spawn(proxy.clone(), move || DesktopService {
+        last_active_windows: Arc::new(Mutex::new(HashMap::new())),
+        on_demand,
+    });
 
-        // Poll for tray events
-        match TrayIconEvent::receiver().try_recv() {
-            Ok(tray_event) => {
-                info!("Tray Event Received: id='{}', rect={:?}", tray_event.id.0, tray_event.icon_rect);
-                match tray_event.id.0.as_str() { 
-                    MENU_ID_ABOUT_STR => {
-                        info!("'About DeskHopper' menu item clicked.");
-                        show_about_dialog();
-                    }
-                    MENU_ID_EXIT_STR => {
-                        info!("'Exit' menu item clicked. Shutting down.");
-                        *control_flow = ControlFlow::Exit;
-                    }
-                    _ => {
-                        debug!("Unhandled tray event ID: '{}'", tray_event.id.0);
-                    }
-                }
-            }
-            Err(_e) => {
-                //error!("Error receiving tray event: {:?}", e);
+    let keymap = Keymap::desktop();
+    let mut mode_keys = HashMap::new();
+    for key in keymap.keys() {
+        match hotkeys::mode_hotkey(key) {
+            Ok(hotkey) => {
+                mode_keys.insert(hotkey.id(), (key, hotkey));
             }
+            Err(e) => warn!("No hotkey for mode key {:?}: {}", key, e),
         }
+    }
 
-        match MenuEvent::receiver().try_recv() {
-            Ok(event) => {
-                info!("menu event: {:?}", event);
+    if !config.desktops.layout.is_empty() {
+        executor.submit(Action::ApplyLayout(config.desktops.layout.clone()), None);
+    }
 
-                match event.id.0.as_str() {
-                    MENU_ID_ABOUT_STR => {
-                        info!("'About DeskHopper' menu item clicked.");
-                        show_about_dialog();
-                    }
-                    MENU_ID_EXIT_STR => {
-                        info!("'Exit' menu item clicked. Shutting down.");
-                        *control_flow = ControlFlow::Exit;
-                    }
-                    _ => {
-                        debug!("Unhandled tray event ID: '{}'", event.id.0);
-                    }
-                }
-            }
-            Err(_e) => {
-                //error!("Error receiving menu event: {:?}", e);
-            }
-        }
+    info!("Event loop starting. Application is running in the background.");
+
+    let mut app = App {
+        tray,
+        hotkeys,
+        gestures: GestureTracker::new(
+            Duration::from_millis(config.hotkeys.long_press_ms),
+            Duration::from_millis(config.hotkeys.double_tap_ms),
+        ),
+        modes: ModeMachine::new(keymap, Duration::from_millis(config.hotkeys.mode_timeout_ms)),
+        mode_keys,
+        executor,
+        switches_in_flight: 0,
+        pending_ipc_reply: None,
+        exit_requested: false,
+        backend: BackendHealth::new(RECONNECT_INITIAL_DELAY, RECONNECT_MAX_DELAY),
+        pending_focus: None,
+        confirmed_desktop: None,
+        last_switch_seq: 0,
+        switch_confirm_timeout: Duration::from_millis(config.switch.confirm_timeout_ms),
+        desktop_event_thread,
+        cleanup: config.desktops.cleanup,
+        collector,
+        rules: config.rules,
+        apps: config.apps,
+        placed_windows: HashSet::new(),
+        proxy: proxy.clone(),
+    };
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
 
         match event {
             Event::NewEvents(_) => (),
@@ -203,228 +1318,198 @@ fn main() -> Result<()> {
             }
             Event::UserEvent(custom_event) => {
                 match custom_event {
-                    // Match on the single HotkeyTriggered variant
-                    CustomEvent::HotkeyTriggered(id) => {
-                        if let Some(action) = hotkey_actions.get(&id) {
-                            match action {
-                                HotkeyAction::Switch(desktop_idx) => {
-                                    info!("Switch Hotkey ID {} pressed, switching to desktop index {}", id, desktop_idx);
-                                    handle_switch_to_desktop(*desktop_idx, &last_active_windows_map_for_loop);
-                                }
-                                HotkeyAction::MoveWindow(desktop_idx) => {
-                                    info!("Move Window Hotkey ID {} pressed, moving window to desktop index {}", id, desktop_idx);
-                                    handle_move_window_to_desktop(*desktop_idx);
+                    CustomEvent::HotkeyEvent(id, state, at) => app.hotkey_event(id, state, at),
+                    CustomEvent::GestureTimer => {
+                        for (id, gesture) in app.gestures.poll(Instant::now()) {
+                            app.run_gesture(id, gesture);
+                        }
+                        app.schedule_gesture_timer();
+                    }
+                    CustomEvent::ModeTimer => {
+                        if app.modes.poll(Instant::now()) {
+                            info!("Mode timed out.");
+                            app.left_mode();
+                        }
+                    }
+                    CustomEvent::DesktopsChanged => {
+                        refresh_tray_desktops(&mut app.tray);
+                    }
+                    CustomEvent::CurrentDesktopChanged { old, new } => {
+                        refresh_tray_desktops(&mut app.tray);
+                        app.confirmed_desktop = new;
+                        app.switch_confirmed();
+                        if let (Some(old), Some(new)) = (old, new) {
+                            app.desktop_left(old, new);
+                        }
+                    }
+                    CustomEvent::CollectDesktops => app.executor.submit(Action::CollectDesktops, None),
+                    CustomEvent::SwitchTimeout(seq) => app.switch_timed_out(seq),
+                    CustomEvent::ReconnectBackend => app.reconnect_backend(),
+                    CustomEvent::WindowShown(hwnd) => app.window_shown(hwnd),
+                    CustomEvent::RetryHotkeys => {
+                        if app.hotkeys.has_retryable() && app.hotkeys.retry_failed() > 0 {
+                            app.tray.set_hotkey_failures(app.hotkeys.failed_count());
+                        }
+                    }
+                    CustomEvent::TrayIconEvent(tray_event) => {
+                        // Clicks on menu items arrive as MenuEvents.
+                        debug!("Tray Event Received: id='{}', click={:?}", tray_event.id.0, tray_event.click_type);
+                    }
+                    CustomEvent::MenuEvent(event) => {
+                        info!("menu event: {:?}", event);
+                        match tray::menu_command(event.id.0.as_str()) {
+                            Some(command) => {
+                                match dispatch(command, CommandSource::TrayMenu, &mut app) {
+                                    Ok(Some(output)) => show_output_dialog(output),
+                                    Ok(None) => (),
+                                    Err(e) => app.report_failure(&e),
                                 }
+                                // Clicking a check item toggles it, so resync even if nothing changed.
+                                refresh_tray_desktops(&mut app.tray);
                             }
-                        } else {
-                            warn!("Received unknown hotkey ID via UserEvent: {}", id);
+                            None => debug!("Unhandled menu event ID: '{}'", event.id.0),
                         }
                     }
+                    CustomEvent::IpcRequest(request) => {
+                        app.pending_ipc_reply = Some(request.reply_to);
+                        let result = dispatch(request.command, CommandSource::Ipc, &mut app);
+                        // Unless a queued action took the channel to reply once it's done.
+                        if let Some(reply_to) = app.pending_ipc_reply.take() {
+                            // Failures go back to the client rather than to a notification.
+                            let reply = match result {
+                                Ok(output) => ipc::ok_reply(output),
+                                Err(e) => {
+                                    app.track_outage(&e);
+                                    ipc::error_reply(&e)
+                                }
+                            };
+                            // The client may have given up waiting; nothing to do then.
+                            let _ = reply_to.send(reply);
+                        }
+                    }
+                    CustomEvent::ActionFinished(finished) => app.action_finished(finished),
                 }
             }
             _ => (),
         }
+
+        if app.exit_requested {
+            *control_flow = ControlFlow::Exit;
+        }
     }); 
     
     #[allow(unreachable_code)]
     Ok(())
 } 
 
-fn setup_tray_icon() -> Result<tray_icon::TrayIcon> {
-    let icon_data = load_tray_icon().context("Failed to load icon for tray")?;
-    // `menu` does not need to be mutable as `append` takes `&self` and returns `Result<&Self>`.
-    let menu = Menu::new(); 
-
-    // MenuItem::new from tray_icon::menu (which is muda::MenuItem)
-    // takes (text: S, enabled: bool, accelerator: Option<Accelerator>)
-    // The string used for `text` is what MenuId will wrap if not specified otherwise.
-    // The TrayIconEvent.id.0 will be this string.
-    
-    let about_item = MenuItem::with_id(MENU_ID_ABOUT_STR, MENU_ID_ABOUT_STR, true, None::<Accelerator>); 
-    menu.append(&about_item).context("Failed to append About item")?;
-
-    menu.append(&PredefinedMenuItem::separator()).context("Failed to append separator")?;
-
-    let exit_item = MenuItem::with_id(MENU_ID_EXIT_STR, MENU_ID_EXIT_STR, true, None::<Accelerator>);
-    menu.append(&exit_item).context("Failed to append Exit item")?;
-
-    let tray_instance = TrayIconBuilder::new()
-        .with_menu(Box::new(menu)) 
-        .with_tooltip(TRAY_ICON_TOOLTIP)
-        .with_icon(icon_data) 
-        .build()
-        .context("Failed to build system tray icon")?;
-    Ok(tray_instance)
-}
-
-fn register_hotkeys(
-    manager: &mut GlobalHotKeyManager,
-    actions: &mut HashMap<u32, HotkeyAction>, 
-    registered_vec: &mut Vec<HotKey>, 
-) -> Result<()> {
-    info!("Registering hotkeys...");
-
-    for i in 1..=9 {
-        let code = number_to_code(i).context(format!("Invalid number for code: {}", i))?;
-        let hotkey = HotKey::new(Some(Modifiers::CONTROL), code);
-        manager.register(hotkey).context(format!("Failed to register RCtrl+{}", i))?;
-        actions.insert(hotkey.id(), HotkeyAction::Switch((i - 1) as usize)); 
-        registered_vec.push(hotkey); 
-        info!("Registered SWITCH RCtrl+{} -> Desktop Index {}", i, i - 1);
-    }
-    let hotkey_0_switch = HotKey::new(Some(Modifiers::CONTROL), Code::Digit0);
-    manager.register(hotkey_0_switch).context("Failed to register RCtrl+0 for SWITCH")?;
-    actions.insert(hotkey_0_switch.id(), HotkeyAction::Switch(9)); 
-    registered_vec.push(hotkey_0_switch); 
-    info!("Registered SWITCH RCtrl+0 -> Desktop Index 9");
-
-    for i in 1..=9 {
-        let code = number_to_code(i).context(format!("Invalid number for code: {}", i))?;
-        let hotkey = HotKey::new(Some(Modifiers::CONTROL | Modifiers::SHIFT), code);
-        manager.register(hotkey).context(format!("Failed to register RCtrl+Shift+{}", i))?;
-        actions.insert(hotkey.id(), HotkeyAction::MoveWindow((i - 1) as usize));
-        registered_vec.push(hotkey);
-        info!("Registered MOVE RCtrl+Shift+{} -> Desktop Index {}", i, i - 1);
-    }
-    let hotkey_0_move = HotKey::new(Some(Modifiers::CONTROL | Modifiers::SHIFT), Code::Digit0);
-    manager.register(hotkey_0_move).context("Failed to register RCtrl+Shift+0 for MOVE")?;
-    actions.insert(hotkey_0_move.id(), HotkeyAction::MoveWindow(9));
-    registered_vec.push(hotkey_0_move);
-    info!("Registered MOVE RCtrl+Shift+0 -> Desktop Index 9");
-
-    info!("All hotkeys registration attempted.");
-    Ok(())
-}
-
-fn number_to_code(num: u32) -> Result<Code> {
-    match num {
-        1 => Ok(Code::Digit1), 2 => Ok(Code::Digit2), 3 => Ok(Code::Digit3),
-        4 => Ok(Code::Digit4), 5 => Ok(Code::Digit5), 6 => Ok(Code::Digit6),
-        7 => Ok(Code::Digit7), 8 => Ok(Code::Digit8), 9 => Ok(Code::Digit9),
-        0 => Ok(Code::Digit0),
-        _ => Err(anyhow::anyhow!("Number out of range for hotkey code")),
-    }
+// Desktop notifications from explorer keep the tray menu in sync. The returned thread
+// handle must stay alive for as long as we want to receive events.
+fn start_desktop_event_listener(proxy: &EventLoopProxy<CustomEvent>) -> Option<DesktopEventThread> {
+    let (desktop_event_tx, desktop_event_rx) = std::sync::mpsc::channel::<DesktopEvent>();
+    let desktop_event_thread = match winvd::listen_desktop_events(desktop_event_tx) {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Failed to listen for virtual desktop events: {:?}. Tray menu will not update.", e);
+            return None;
+        }
+    };
+    let desktop_event_proxy = proxy.clone();
+    // Ends when the listener is dropped, which closes the channel.
+    thread::spawn(move || {
+        info!("Desktop event forwarder thread started.");
+        for event in desktop_event_rx {
+            debug!("DesktopEvent received: {:?}", event);
+            let custom_event = match event {
+                // Fires constantly and doesn't affect the desktop list.
+                DesktopEvent::WindowChanged(_) => continue,
+                DesktopEvent::DesktopChanged { new, old } => {
+                    CustomEvent::CurrentDesktopChanged { old: old.get_id().ok(), new: new.get_id().ok() }
+                }
+                _ => CustomEvent::DesktopsChanged,
+            };
+            if desktop_event_proxy.send_event(custom_event).is_err() {
+                error!("Failed to send desktop event to main loop. Main loop likely exited.");
+                break;
+            }
+        }
+        info!("Desktop event forwarder thread finished.");
+    });
+    Some(desktop_event_thread)
 }
 
-fn handle_switch_to_desktop(target_desktop_idx_0_based: usize, last_active_map: &LastActiveWindowMap) {
-    info!("Attempting to SWITCH to desktop index: {}", target_desktop_idx_0_based);
+fn handle_switch_to_desktop(
+    target_desktop_idx_0_based: usize,
+    last_active_map: &LastActiveWindowMap,
+    on_demand: &OnDemandDesktops,
+) -> Result<(), DeskError> {
+    info!("Attempting to SWITCH to {}", describe_desktop(target_desktop_idx_0_based));
 
     // 1. Store the current foreground window for the *current* desktop before switching
-    if let Ok(current_desktop_before_switch) = winvd::get_current_desktop() {
-        let current_desktop_id_before_switch = current_desktop_before_switch.get_index();
-        let current_fg_hwnd = unsafe { GetForegroundWindow() };
-        if current_fg_hwnd.0 != std::ptr::null_mut() { // Check if HWND is not null
-             info!("Remembering HWND {:?} for desktop ID {:?}", current_fg_hwnd, current_desktop_id_before_switch);
-            let mut map_guard = last_active_map.lock().unwrap_or_else(|poisoned| {
-                warn!("Mutex for last_active_windows_map was poisoned in handle_switch (store). Recovering.");
-                poisoned.into_inner()
-            });
-            map_guard.insert(current_desktop_id_before_switch.unwrap(), current_fg_hwnd);
-        }
-    } else {
-        warn!("Could not get current desktop ID before switch to store last active window.");
-    }
-
-    let mut switched_successfully = false;
-
-    match get_desktop_count() {
-        Ok(current_count_u32) => {
-            let current_count = current_count_u32 as usize; 
-            info!("Current virtual desktop count: {}", current_count);
-            if target_desktop_idx_0_based < current_count {
-                match switch_desktop(target_desktop_idx_0_based as u32) { 
-                    Ok(_) => {
-                        info!("Switched to desktop index {} successfully.", target_desktop_idx_0_based);
-                        switched_successfully = true;
-                    },
-                    Err(e) => error!("Failed to switch to desktop index {}: {:?}", target_desktop_idx_0_based, e),
-                }
-            } else {
-                info!(
-                    "Target desktop index {} is out of range (current count {}). Creating new desktops.",
-                    target_desktop_idx_0_based, current_count
-                );
-                let desktops_to_create = target_desktop_idx_0_based - current_count + 1;
-                for i in 0..desktops_to_create {
-                    match create_desktop() {
-                        Ok(new_desktop_id) => info!(
-                            "Created new desktop (iteration {}/{}), ID: {:?}",
-                            i + 1, desktops_to_create, new_desktop_id
-                        ),
-                        Err(e) => {
-                            error!("Failed to create new desktop (iteration {}): {:?}", i + 1, e);
-                            show_message_box(
-                                "Desktop Creation Error",
-                                &format!("Failed to create a new virtual desktop: {:?}.\nPlease ensure resources and permissions.", e),
-                                MB_ICONERROR);
-                            return;
-                        }
-                    }
-                }
-                match switch_desktop(target_desktop_idx_0_based as u32) {
-                    Ok(_) => {
-                        info!("Switched to newly created desktop index {} successfully.", target_desktop_idx_0_based);
-                        switched_successfully = true;
-                    }
-                    Err(e) => error!("Failed to switch to newly created desktop index {}: {:?}", target_desktop_idx_0_based, e),
-                }
+    match winvd::get_current_desktop().and_then(|desktop| desktop.get_index()) {
+        Ok(current_desktop_id_before_switch) => {
+            let current_fg_hwnd = unsafe { GetForegroundWindow() };
+            // A pinned window is on every desktop, so it says nothing about this one.
+            if current_fg_hwnd.0 != std::ptr::null_mut() && !window::is_pinned(current_fg_hwnd) { // Check if HWND is not null
+                info!("Remembering HWND {:?} for desktop ID {:?}", current_fg_hwnd, current_desktop_id_before_switch);
+                let mut map_guard = last_active_map.lock().unwrap_or_else(|poisoned| {
+                    warn!("Mutex for last_active_windows_map was poisoned in handle_switch (store). Recovering.");
+                    poisoned.into_inner()
+                });
+                map_guard.insert(current_desktop_id_before_switch, current_fg_hwnd);
             }
         }
-        Err(e) => {
-            error!("Failed to get virtual desktop count: {:?}", e);
-            show_message_box(
-                "Virtual Desktop Error",
-                &format!("Failed to get virtual desktop count: {:?}.\nSwitching aborted.", e),
-                MB_ICONERROR);
-        }
+        Err(e) => warn!("Could not get current desktop ID before switch to store last active window: {:?}", e),
     }
 
-    if switched_successfully {
-        info!("Switched successfully");
-        // Get the ID of the desktop we just switched to
-        let new_desktop_id_for_focus = match winvd::get_current_desktop() {
-            Ok(current_desktop) => Some(current_desktop.get_index().unwrap()),
-            Err(e) => {
-                warn!("Could not determine current desktop ID after switch to attempt focus: {:?}", e);
-                None
-            }
-        };
+    ensure_desktop_exists(target_desktop_idx_0_based, on_demand)?;
+    switch_desktop(target_desktop_idx_0_based as u32)
+        .map_err(|e| DeskError::for_desktop(target_desktop_idx_0_based, e))?;
+    info!("Switched to {} successfully.", describe_desktop(target_desktop_idx_0_based));
+    Ok(())
+}
 
-        info!("New desktop {:?}", new_desktop_id_for_focus);
+// Where a listed window shows up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WindowPlace {
+    /// On the desktop with this 0-based index.
+    Desktop(usize),
+    /// Pinned, so on every desktop.
+    Everywhere,
+}
 
-        if let Some(desktop_id_to_focus) = new_desktop_id_for_focus {
-            std::thread::sleep(std::time::Duration::from_millis(100)); 
-            if let Err(e) = focus_a_window_on_current_desktop(desktop_id_to_focus, last_active_map) {
-                warn!("Could not focus a window on the new desktop {}: {}", target_desktop_idx_0_based, e);
-            }
-        } else {
-            warn!("Could not determine current desktop ID after switch to attempt focus.");
+// The visible, titled top-level windows in Z order, with where each one shows up. Windows
+// that belong to no desktop, e.g. hidden UWP hosts, are left out. Desktops are looked up
+// as the iterator is consumed, so stopping early saves the calls for the rest.
+fn desktop_windows() -> impl Iterator<Item = (HWND, WindowPlace)> {
+    window::top_level_windows().into_iter().filter_map(|hwnd| {
+        if window::is_pinned(hwnd) {
+            return Some((hwnd, WindowPlace::Everywhere));
         }
-    }
+        let desktop_idx = get_desktop_by_window(hwnd).ok()?.get_index().ok()? as usize;
+        Some((hwnd, WindowPlace::Desktop(desktop_idx)))
+    })
 }
 
-extern "system" fn enum_windows_proc_focus(hwnd: HWND, lparam: LPARAM) -> BOOL {
-    let data = unsafe { &mut *(lparam.0 as *mut EnumCallbackData) };
-    if data.found_hwnd.is_some() { return FALSE; }
-
-    if unsafe { IsWindowVisible(hwnd) }.as_bool() {
-        let style = unsafe { GetWindowLongW(hwnd, GWL_STYLE) } as u32;
-        if (style & WS_CHILD.0) != 0 { return TRUE; }
-        
-        let mut title_buffer: [u16; 128] = [0; 128];
-        if unsafe { GetWindowTextW(hwnd, &mut title_buffer) } == 0 { return TRUE; }
+// Finds a visible, titled top-level window on the desktop with this 0-based index.
+// Pinned windows count as on every desktop; focusing one would hide what's really here.
+fn find_window_on_desktop(desktop_idx: u32) -> Option<HWND> {
+    let (hwnd, _) = desktop_windows().find(|(_, place)| *place == WindowPlace::Desktop(desktop_idx as usize))?;
+    info!("Found candidate window {:?} ('{}') on {}.", hwnd, window::title(hwnd), describe_desktop(desktop_idx as usize));
+    Some(hwnd)
+}
 
-        match get_desktop_by_window(hwnd) {
-            Ok(desktop) if desktop.get_index().unwrap() == data.target_desktop_id => {
-                info!("EnumWindows: Found candidate window {:?} ('{}') on target desktop {:?}.", 
-                    hwnd, OsString::from_wide(&title_buffer).to_string_lossy(), desktop.get_index().unwrap());
-                data.found_hwnd = Some(hwnd);
-                return FALSE; 
-            }
-            _ => {}
+// Focuses the remembered (or some other) window on whatever desktop is now current.
+fn focus_current_desktop(last_active_map: &LastActiveWindowMap) {
+    let desktop_id = match winvd::get_current_desktop().and_then(|desktop| desktop.get_index()) {
+        Ok(desktop_id) => desktop_id,
+        Err(e) => {
+            warn!("Could not determine current desktop ID after switch to attempt focus: {:?}", e);
+            return;
         }
+    };
+    if let Err(e) = focus_a_window_on_current_desktop(desktop_id, last_active_map) {
+        warn!("Could not focus a window on desktop {}: {}", desktop_id, e);
     }
-    TRUE 
 }
 
 // Updated to accept and use the last_active_map
@@ -448,9 +1533,9 @@ fn focus_a_window_on_current_desktop(current_desktop_id: u32, last_active_map: &
         let style = unsafe { GetWindowLongW(remembered_hwnd, GWL_STYLE) } as u32;
         let is_not_child = (style & WS_CHILD.0) == 0;
 
-        if is_valid_window && is_visible && is_not_child {
-            match get_desktop_by_window(remembered_hwnd) {
-                Ok(desktop_id_of_remembered) if desktop_id_of_remembered.get_index().unwrap() == current_desktop_id => {
+        if is_valid_window && is_visible && is_not_child && !window::is_pinned(remembered_hwnd) {
+            match get_desktop_by_window(remembered_hwnd).and_then(|desktop| desktop.get_index()) {
+                Ok(desktop_id_of_remembered) if desktop_id_of_remembered == current_desktop_id => {
                     info!("Attempting to set foreground to remembered window: {:?}", remembered_hwnd);
                     unsafe { BringWindowToTop(remembered_hwnd) };
                     if unsafe { SetForegroundWindow(remembered_hwnd) }.as_bool() {
@@ -472,14 +1557,7 @@ fn focus_a_window_on_current_desktop(current_desktop_id: u32, last_active_map: &
     
     // 2. Fallback: Enumerate windows if remembered window focus failed or no remembered window
     info!("Falling back to EnumWindows to find a window on desktop ID: {:?}", current_desktop_id);
-    let mut callback_data = EnumCallbackData {
-        target_desktop_id: current_desktop_id,
-        found_hwnd: None,
-    };
-
-    unsafe { EnumWindows(Some(enum_windows_proc_focus), LPARAM(&mut callback_data as *mut _ as isize)) };
-
-    if let Some(hwnd_to_focus) = callback_data.found_hwnd {
+    if let Some(hwnd_to_focus) = find_window_on_desktop(current_desktop_id) {
         info!("EnumWindows found: {:?}. Attempting to set foreground.", hwnd_to_focus);
         unsafe { BringWindowToTop(hwnd_to_focus) }; 
         if unsafe { SetForegroundWindow(hwnd_to_focus) }.as_bool() {
@@ -493,72 +1571,171 @@ fn focus_a_window_on_current_desktop(current_desktop_id: u32, last_active_map: &
     Ok(())
 }
 
-fn handle_move_window_to_desktop(target_desktop_idx_0_based: usize) {
-    info!("Attempting to MOVE foreground window to desktop index: {}", target_desktop_idx_0_based);
-    
-    let hwnd = unsafe { GetForegroundWindow() };
+fn handle_move_window_to_desktop(target_desktop_idx_0_based: usize, hwnd: HWND, on_demand: &OnDemandDesktops) -> Result<(), DeskError> {
+    info!("Attempting to MOVE window {:?} to {}", hwnd, describe_desktop(target_desktop_idx_0_based));
+
     if hwnd.0 == std::ptr::null_mut() {
-        error!("Failed to get foreground window handle.");
-        return;
-    }
-    info!("Foreground window HWND: {:?}", hwnd);
-
-    match get_desktop_count() {
-        Ok(current_count_u32) => {
-            let current_count = current_count_u32 as usize;
-            if target_desktop_idx_0_based >= current_count {
-                info!(
-                    "Target desktop index {} for MOVE is out of range (current count {}). Creating new desktops.",
-                    target_desktop_idx_0_based, current_count
-                );
-                let desktops_to_create = target_desktop_idx_0_based - current_count + 1;
-                for i in 0..desktops_to_create {
-                    match create_desktop() {
-                        Ok(_new_desktop_id) => info!(
-                            "Created new desktop for MOVE (iteration {}/{})",
-                             i + 1, desktops_to_create
-                        ),
-                        Err(e) => {
-                            error!("Failed to create new desktop for MOVE (iteration {}): {:?}", i + 1, e);
-                            show_message_box(
-                                "Desktop Creation Error",
-                                &format!("Failed to create a new virtual desktop for move: {:?}.", e),
-                                MB_ICONERROR);
-                            return;
-                        }
-                    }
-                }
+        return Err(DeskError::NoActiveWindow);
+    }
+
+    ensure_desktop_exists(target_desktop_idx_0_based, on_demand)?;
+    match move_window_to_desktop(target_desktop_idx_0_based as u32, &hwnd) {
+        Ok(_) => {
+            info!("Successfully moved window {:?} to {}.", hwnd, describe_desktop(target_desktop_idx_0_based));
+            Ok(())
+        }
+        Err(e) => {
+            warn!("Failed to move window {:?} to {}: {:?}", hwnd, describe_desktop(target_desktop_idx_0_based), e);
+            let error = match e {
+                winvd::Error::WindowNotFound => DeskError::for_window(hwnd.0 as isize, e),
+                other => DeskError::for_desktop(target_desktop_idx_0_based, other),
+            };
+            // A lost connection to the desktop service says nothing about the window itself.
+            if error.is_retryable() || matches!(error, DeskError::DesktopNotFound(_) | DeskError::WindowNotFound(_)) {
+                return Err(error);
             }
-            match move_window_to_desktop(target_desktop_idx_0_based as u32, &hwnd) {
-                Ok(_) => info!("Successfully moved window {:?} to desktop index {}.", hwnd, target_desktop_idx_0_based),
-                Err(e) => {
-                    error!("Failed to move window {:?} to desktop index {}: {:?}", hwnd, target_desktop_idx_0_based, e);
-                    show_message_box(
-                        "Move Window Error",
-                        &format!("Failed to move window: {:?}.\nEnsure the window is valid and not minimized/special.", e),
-                        MB_ICONERROR);
-                }
+            Err(DeskError::WindowNotMovable(window::not_movable_reason(hwnd)))
+        }
+    }
+}
+
+// Appends desktops until `target_desktop_idx_0_based` exists, as far as the policy allows.
+fn ensure_desktop_exists(target_desktop_idx_0_based: usize, on_demand: &OnDemandDesktops) -> Result<(), DeskError> {
+    let current_count = get_desktop_count()? as usize;
+    let desktops_to_create = match on_demand.policy.plan(target_desktop_idx_0_based, current_count)? {
+        CreatePlan::Exists => return Ok(()),
+        CreatePlan::Create(n) => n,
+        CreatePlan::Confirm(n) => {
+            if !confirm_desktop_creation(target_desktop_idx_0_based, n) {
+                info!("Creating {} was declined.", describe_desktop(target_desktop_idx_0_based));
+                return Err(DeskError::CreationDeclined(target_desktop_idx_0_based));
             }
+            n
         }
-        Err(e) => {
-            error!("Failed to get virtual desktop count for MOVE operation: {:?}", e);
-             show_message_box(
-                "Virtual Desktop Error",
-                &format!("Failed to get virtual desktop count for move: {:?}.\nMove aborted.", e),
-                MB_ICONERROR);
+    };
+    info!(
+        "Target {} is out of range (current count {}). Creating {} new desktop(s).",
+        describe_desktop(target_desktop_idx_0_based), current_count, desktops_to_create
+    );
+    for i in 0..desktops_to_create {
+        let new_desktop = create_one_desktop()?;
+        info!("Created new desktop (iteration {}/{}), ID: {:?}", i + 1, desktops_to_create, new_desktop);
+        if let Ok(id) = new_desktop.get_id() {
+            on_demand.collector.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).created(id);
         }
     }
+    Ok(())
+}
+
+// Appends a desktop. Any failure other than an outage counts as a failure to create it.
+fn create_one_desktop() -> Result<winvd::Desktop, DeskError> {
+    create_desktop().map_err(|e| {
+        let detail = format!("{:?}", e);
+        match DeskError::from(e) {
+            error @ DeskError::BackendUnavailable(_) => error,
+            _ => DeskError::CreateDesktopFailed(detail),
+        }
+    })
+}
+
+// Removes the desktops created on demand whose grace period is over, unless they hold
+// windows, are current or, in trailing mode, are followed by a desktop that is kept.
+fn collect_desktops(on_demand: &OnDemandDesktops) -> Result<(), DeskError> {
+    let due = on_demand.collector.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).due(Instant::now());
+    if due.is_empty() {
+        return Ok(());
+    }
+    let desktops = winvd::get_desktops()?
+        .iter()
+        .map(|desktop| desktop.get_id())
+        .collect::<Result<Vec<GUID>, _>>()?;
+    let current = winvd::get_current_desktop()?.get_id()?;
+    let removals = cleanup::select_removals(&due, &desktops, current, on_demand.cleanup, |idx, _| {
+        find_window_on_desktop(idx as u32).is_some()
+    });
+
+    for id in due.iter().filter(|id| !removals.contains(id)) {
+        let mut collector = on_demand.collector.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if desktops.contains(id) {
+            debug!("Keeping desktop {:?}; it is in use.", id);
+            collector.kept(*id);
+        } else {
+            // Removed by the user in the meantime.
+            collector.removed(*id);
+        }
+    }
+    for id in removals {
+        let result = remove_desktop(id, current);
+        let mut collector = on_demand.collector.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match result {
+            Ok(()) => {
+                info!("Removed unused desktop {:?}, which was created on demand.", id);
+                collector.removed(id);
+            }
+            Err(e) => {
+                collector.kept(id);
+                return Err(e.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+// Asks whether to create the desktops needed to reach `target_desktop_idx_0_based`. Blocks
+// the worker until answered, so nothing else switches desktops in the meantime.
+fn confirm_desktop_creation(target_desktop_idx_0_based: usize, desktops_to_create: usize) -> bool {
+    let text = if desktops_to_create == 1 {
+        format!("Desktop {} doesn't exist yet. Create it?", target_desktop_idx_0_based + 1)
+    } else {
+        format!(
+            "Desktop {} doesn't exist yet. Create {} new desktops to reach it?",
+            target_desktop_idx_0_based + 1,
+            desktops_to_create
+        )
+    };
+    let title: Vec<u16> = APP_NAME.encode_utf16().chain(std::iter::once(0)).collect();
+    let text: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+    let answer = unsafe {
+        MessageBoxW(
+            HWND(std::ptr::null_mut()),
+            PCWSTR(text.as_ptr()),
+            PCWSTR(title.as_ptr()),
+            MB_YESNO | MB_ICONQUESTION | MB_SETFOREGROUND | MB_TOPMOST,
+        )
+    };
+    answer == IDYES
 }
 
 fn show_about_dialog() {
+    let desktops = match query_desktops() {
+        Ok((desktops, current_idx)) => desktops
+            .iter()
+            .map(|desktop| {
+                let current = if current_idx == Some(desktop.index) { " (current)" } else { "" };
+                format!("  {}: {}{}\n", desktop.index + 1, desktop.display_name(), current)
+            })
+            .collect(),
+        Err(e) => {
+            warn!("Could not list desktops for the About dialog: {:?}", e);
+            String::new()
+        }
+    };
     let message = format!(
         "{}\nVersion: {}\n\n\
         Allows switching virtual desktops 1-10 using RCtrl + <Number> (RCtrl+0 for Desktop 10).\n\n\
+        Desktops:\n{}\n\
         Author: Joona Kulmala <jmkulmala@gmail.com>.",
         APP_NAME,
-        env!("CARGO_PKG_VERSION")
+        env!("CARGO_PKG_VERSION"),
+        desktops
     );
-    show_message_box("About", &message, MB_ICONINFORMATION);
+    // On its own thread, as the message box blocks until dismissed.
+    thread::spawn(move || show_message_box("About", &message, MB_ICONINFORMATION));
+}
+
+// Shows what a command reported when it wasn't run from the command line.
+fn show_output_dialog(output: String) {
+    thread::spawn(move || show_message_box(APP_NAME, &output, MB_ICONINFORMATION));
 }
 
 fn show_message_box(title: &str, text: &str, flags: MESSAGEBOX_STYLE) {
@@ -0,0 +1,125 @@
+// src/menu.rs
+
+//! Entries for `deskhopper menu`, which hands desktops and windows to a launcher such as
+//! fzf, rofi or fuzzel and carries out the line picked. Each line is a readable label, a
+//! tab and the command it stands for, so launchers search the label and the command
+//! travels along with the selection.
+
+use std::fmt;
+
+use crate::command::{Command, DesktopTarget};
+use crate::DesktopInfo;
+
+/// A window as offered in the menu.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MenuWindow {
+    pub hwnd: isize,
+    /// Where the window is, e.g. "desktop 2 'Mail'".
+    pub place: String,
+    pub exe: String,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MenuEntry {
+    pub label: String,
+    pub command: Command,
+}
+
+impl fmt::Display for MenuEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}", self.label, self.command)
+    }
+}
+
+/// Switching to every desktop other than the current one, then focusing every window.
+/// Moving the active window is left out: by the time a line is picked, the launcher has
+/// the foreground.
+pub fn entries(desktops: &[DesktopInfo], current_idx: Option<usize>, windows: &[MenuWindow]) -> Vec<MenuEntry> {
+    let mut entries: Vec<MenuEntry> = desktops
+        .iter()
+        .filter(|desktop| Some(desktop.index) != current_idx)
+        .map(|desktop| MenuEntry {
+            label: format!("Switch to {}", desktop.display_name()),
+            command: Command::SwitchDesktop(DesktopTarget::Index(desktop.index)),
+        })
+        .collect();
+    for window in windows {
+        entries.push(MenuEntry {
+            label: format!("Focus {} - {} ({})", window.title, window.exe, window.place),
+            command: Command::FocusWindow(format!("{:#x}", window.hwnd)),
+        });
+    }
+    entries
+}
+
+/// The command of a line picked from the menu. Launchers that print only what was typed
+/// leave no tab, in which case the line is taken as a command of its own.
+pub fn parse_selection(line: &str) -> Result<Command, String> {
+    let command = line.rsplit_once('\t').map_or(line, |(_, command)| command);
+    command.trim().parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desktop(index: usize, name: Option<&str>) -> DesktopInfo {
+        DesktopInfo { index, name: name.map(str::to_string) }
+    }
+
+    #[test]
+    fn lists_other_desktops_then_windows() {
+        let desktops = [desktop(0, None), desktop(1, Some("Mail")), desktop(2, None)];
+        let windows = [MenuWindow {
+            hwnd: 0x1a2b,
+            place: "desktop 3".to_string(),
+            exe: "code.exe".to_string(),
+            title: "main.rs".to_string(),
+        }];
+        let lines: Vec<String> = entries(&desktops, Some(1), &windows).iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "Switch to Desktop 1\tswitch 1",
+                "Switch to Desktop 3\tswitch 3",
+                "Focus main.rs - code.exe (desktop 3)\tfocus 0x1a2b",
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_every_desktop_when_the_current_one_is_unknown() {
+        let desktops = [desktop(0, None), desktop(1, None)];
+        let commands: Vec<Command> = entries(&desktops, None, &[]).into_iter().map(|entry| entry.command).collect();
+        assert_eq!(
+            commands,
+            [
+                Command::SwitchDesktop(DesktopTarget::Index(0)),
+                Command::SwitchDesktop(DesktopTarget::Index(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_the_command_after_the_last_tab() {
+        let cases = [
+            ("Switch to Desktop 2\tswitch 2", Command::SwitchDesktop(DesktopTarget::Index(1))),
+            // Window titles may contain tabs of their own.
+            ("Focus a\tb - x.exe (desktop 1)\tfocus 0x10", Command::FocusWindow("0x10".to_string())),
+            ("Switch to Desktop 2\tswitch 2\n", Command::SwitchDesktop(DesktopTarget::Index(1))),
+            // Launchers that print only what was typed.
+            ("switch 3", Command::SwitchDesktop(DesktopTarget::Index(2))),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(parse_selection(line), Ok(expected), "line {:?}", line);
+        }
+    }
+
+    #[test]
+    fn rejects_lines_without_a_command() {
+        assert!(parse_selection("Switch to Desktop 2").is_err());
+        assert!(parse_selection("Switch to Desktop 2\t").is_err());
+        assert!(parse_selection("").is_err());
+    }
+}
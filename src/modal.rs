@@ -0,0 +1,242 @@
+// src/modal.rs
+
+//! Modal keymaps in the style of i3: a leader hotkey enters a named mode in which plain
+//! keys, or short sequences of them, trigger commands. The mode stays active until Escape
+//! is pressed or no key has been pressed for a while. The keys are registered as hotkeys
+//! only while a mode is active, so they type as usual the rest of the time; `ModeMachine`
+//! itself is fed plain characters and timestamps.
+
+use std::time::{Duration, Instant};
+
+use crate::command::{Command, DesktopTarget};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModeKey {
+    Char(char),
+    Escape,
+}
+
+/// The key sequences of a mode and the commands they trigger.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    pub name: &'static str,
+    bindings: Vec<(Vec<char>, Command)>,
+}
+
+impl Keymap {
+    /// The built-in "desktop" mode: a digit switches to that desktop (0 is desktop 10),
+    /// `m` and a digit moves the active window there, `f` and a digit moves it and follows,
+    /// and `n` switches to a new desktop.
+    pub fn desktop() -> Self {
+        let mut bindings = Vec::new();
+        for (digit, desktop_idx) in ('1'..='9').zip(0..).chain(std::iter::once(('0', 9))) {
+            let target = DesktopTarget::Index(desktop_idx);
+            bindings.push((vec![digit], Command::SwitchDesktop(target.clone())));
+            bindings.push((vec!['m', digit], Command::MoveActiveWindow(target.clone())));
+            bindings.push((vec!['f', digit], Command::MoveActiveWindowAndFollow(target)));
+        }
+        bindings.push((vec!['n'], Command::SwitchDesktop(DesktopTarget::New)));
+        Keymap { name: "desktop", bindings }
+    }
+
+    /// Every key the mode reacts to, including Escape.
+    pub fn keys(&self) -> Vec<ModeKey> {
+        let mut keys = vec![ModeKey::Escape];
+        for (sequence, _) in &self.bindings {
+            for c in sequence {
+                if !keys.contains(&ModeKey::Char(*c)) {
+                    keys.push(ModeKey::Char(*c));
+                }
+            }
+        }
+        keys
+    }
+}
+
+/// What a key press in a mode amounted to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModeStep {
+    /// A sequence completed; the mode stays active.
+    Run(Command),
+    /// The keys so far start a longer sequence.
+    Pending,
+    /// The keys so far match nothing; they are discarded.
+    Unbound,
+    /// Escape left the mode.
+    Exited,
+    /// No mode is active.
+    Inactive,
+}
+
+#[derive(Debug)]
+struct ActiveMode {
+    pending: Vec<char>,
+    deadline: Instant,
+}
+
+#[derive(Debug)]
+pub struct ModeMachine {
+    keymap: Keymap,
+    timeout: Duration,
+    active: Option<ActiveMode>,
+}
+
+impl ModeMachine {
+    pub fn new(keymap: Keymap, timeout: Duration) -> Self {
+        ModeMachine { keymap, timeout, active: None }
+    }
+
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    /// The name of the active mode, if any.
+    pub fn current(&self) -> Option<&'static str> {
+        self.active.as_ref().map(|_| self.keymap.name)
+    }
+
+    /// Enters the mode, or restarts its timeout if already in it. Returns true if the mode
+    /// wasn't active before.
+    pub fn enter(&mut self, now: Instant) -> bool {
+        let was_active = self.active.is_some();
+        self.active = Some(ActiveMode { pending: Vec::new(), deadline: now + self.timeout });
+        !was_active
+    }
+
+    /// Leaves the mode. Returns true if it was active.
+    pub fn exit(&mut self) -> bool {
+        self.active.take().is_some()
+    }
+
+    pub fn key(&mut self, key: ModeKey, now: Instant) -> ModeStep {
+        let Some(active) = &mut self.active else { return ModeStep::Inactive };
+        let c = match key {
+            ModeKey::Escape => {
+                self.active = None;
+                return ModeStep::Exited;
+            }
+            ModeKey::Char(c) => c,
+        };
+        active.deadline = now + self.timeout;
+        active.pending.push(c);
+
+        let pending = &active.pending;
+        if let Some((_, command)) = self.keymap.bindings.iter().find(|(sequence, _)| sequence == pending) {
+            active.pending.clear();
+            return ModeStep::Run(command.clone());
+        }
+        if self.keymap.bindings.iter().any(|(sequence, _)| sequence.starts_with(pending)) {
+            return ModeStep::Pending;
+        }
+        active.pending.clear();
+        ModeStep::Unbound
+    }
+
+    /// Leaves the mode if no key was pressed within the timeout. Returns true if it did.
+    pub fn poll(&mut self, now: Instant) -> bool {
+        match &self.active {
+            Some(active) if now >= active.deadline => {
+                self.active = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// When the mode times out, if one is active.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.active.as_ref().map(|active| active.deadline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    fn active_machine(now: Instant) -> ModeMachine {
+        let mut modes = ModeMachine::new(Keymap::desktop(), TIMEOUT);
+        assert!(modes.enter(now));
+        modes
+    }
+
+    #[test]
+    fn runs_single_keys_and_sequences() {
+        let now = Instant::now();
+        let mut modes = active_machine(now);
+        assert_eq!(
+            modes.key(ModeKey::Char('0'), now),
+            ModeStep::Run(Command::SwitchDesktop(DesktopTarget::Index(9)))
+        );
+        assert_eq!(modes.key(ModeKey::Char('m'), now), ModeStep::Pending);
+        assert_eq!(
+            modes.key(ModeKey::Char('3'), now),
+            ModeStep::Run(Command::MoveActiveWindow(DesktopTarget::Index(2)))
+        );
+        assert_eq!(modes.current(), Some("desktop"));
+    }
+
+    #[test]
+    fn discards_unbound_sequences() {
+        let now = Instant::now();
+        let mut modes = active_machine(now);
+        assert_eq!(modes.key(ModeKey::Char('m'), now), ModeStep::Pending);
+        assert_eq!(modes.key(ModeKey::Char('x'), now), ModeStep::Unbound);
+        // Nothing of the discarded sequence is left over.
+        assert_eq!(
+            modes.key(ModeKey::Char('2'), now),
+            ModeStep::Run(Command::SwitchDesktop(DesktopTarget::Index(1)))
+        );
+    }
+
+    #[test]
+    fn escape_leaves_the_mode() {
+        let now = Instant::now();
+        let mut modes = active_machine(now);
+        assert_eq!(modes.key(ModeKey::Escape, now), ModeStep::Exited);
+        assert_eq!(modes.current(), None);
+        assert_eq!(modes.key(ModeKey::Char('2'), now), ModeStep::Inactive);
+        assert!(!modes.exit());
+    }
+
+    #[test]
+    fn times_out_without_key_presses() {
+        let start = Instant::now();
+        let mut modes = active_machine(start);
+        assert!(!modes.poll(start + TIMEOUT / 2));
+        // A key press pushes the deadline back.
+        modes.key(ModeKey::Char('m'), start + TIMEOUT / 2);
+        assert_eq!(modes.deadline(), Some(start + TIMEOUT / 2 + TIMEOUT));
+        assert!(!modes.poll(start + TIMEOUT));
+        assert!(modes.poll(start + TIMEOUT / 2 + TIMEOUT));
+        assert_eq!(modes.current(), None);
+        assert_eq!(modes.deadline(), None);
+        assert!(!modes.poll(start + TIMEOUT * 3));
+    }
+
+    #[test]
+    fn entering_again_restarts_the_timeout() {
+        let start = Instant::now();
+        let mut modes = active_machine(start);
+        modes.key(ModeKey::Char('f'), start);
+        assert!(!modes.enter(start + TIMEOUT / 2));
+        assert_eq!(modes.deadline(), Some(start + TIMEOUT / 2 + TIMEOUT));
+        assert!(!modes.poll(start + TIMEOUT));
+        // The half-typed sequence was dropped.
+        assert_eq!(
+            modes.key(ModeKey::Char('4'), start + TIMEOUT),
+            ModeStep::Run(Command::SwitchDesktop(DesktopTarget::Index(3)))
+        );
+    }
+
+    #[test]
+    fn keys_cover_the_keymap() {
+        let keys = Keymap::desktop().keys();
+        assert_eq!(keys[0], ModeKey::Escape);
+        for c in "0123456789mfn".chars() {
+            assert!(keys.contains(&ModeKey::Char(c)), "{}", c);
+        }
+        assert_eq!(keys.len(), 14);
+    }
+}
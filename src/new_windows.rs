@@ -0,0 +1,60 @@
+// src/new_windows.rs
+
+//! Reports windows as they appear, so window rules can place them. Windows often get
+//! their title only after they are created, so a window counts as new when it is first
+//! shown rather than when it is created. Only top-level windows without an owner are
+//! reported; dialogs stay with the window that opened them.
+
+use anyhow::Result;
+use log::{debug, error};
+use std::cell::RefCell;
+use tao::event_loop::EventLoopProxy;
+use windows::Win32::{
+    Foundation::HWND,
+    UI::{
+        Accessibility::HWINEVENTHOOK,
+        WindowsAndMessaging::{CHILDID_SELF, EVENT_OBJECT_SHOW, OBJID_WINDOW},
+    },
+};
+
+use crate::window;
+use crate::CustomEvent;
+
+thread_local! {
+    // Out-of-context hooks call back on the thread that installed them, so the proxy only
+    // needs to live there.
+    static PROXY: RefCell<Option<EventLoopProxy<CustomEvent>>> = const { RefCell::new(None) };
+}
+
+/// Installs the hook that sends `CustomEvent::WindowShown` for each new window. Must be
+/// called on a thread that pumps messages (the event loop thread), and only once.
+pub fn start_watching(proxy: EventLoopProxy<CustomEvent>) -> Result<()> {
+    PROXY.with(|cell| *cell.borrow_mut() = Some(proxy));
+    window::install_event_hook(EVENT_OBJECT_SHOW, Some(window_shown))
+}
+
+extern "system" fn window_shown(
+    _hook: HWINEVENTHOOK,
+    _event: u32,
+    hwnd: HWND,
+    id_object: i32,
+    id_child: i32,
+    _event_thread: u32,
+    _event_time: u32,
+) {
+    // The event also fires for carets, cursors and controls inside windows.
+    if id_object != OBJID_WINDOW.0 || id_child != CHILDID_SELF as i32 || hwnd.is_invalid() {
+        return;
+    }
+    if !window::is_top_level(hwnd) || window::is_owned(hwnd) {
+        return;
+    }
+    debug!("Window {:?} was shown", hwnd);
+    PROXY.with(|cell| {
+        if let Some(proxy) = cell.borrow().as_ref() {
+            if proxy.send_event(CustomEvent::WindowShown(hwnd.0 as isize)).is_err() {
+                error!("Failed to send WindowShown event. Main loop likely exited.");
+            }
+        }
+    });
+}
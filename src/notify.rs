@@ -0,0 +1,214 @@
+// src/notify.rs
+
+//! Reports errors and notices to the user without blocking the caller. Depending on the
+//! configured policy, reports become desktop notifications (Windows toasts, or libnotify
+//! over D-Bus on Linux) in addition to log lines, are only logged, or are logged at debug
+//! level only. Repeats of the same report and bursts of reports are throttled, so a
+//! failure that recurs on every keypress shows up once.
+
+use log::{debug, error, warn};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{mpsc, Mutex, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::config::NotificationsConfig;
+use crate::APP_NAME;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyPolicy {
+    /// Show a desktop notification (and log).
+    Toast,
+    /// Only write to the log.
+    LogOnly,
+    /// Only log at debug level.
+    Silent,
+}
+
+/// Decides which reports get through: an identical report is suppressed for `dedup_window`
+/// after it was last let through, and at most `max_per_window` reports pass per `window`.
+#[derive(Debug)]
+pub struct RateLimiter {
+    dedup_window: Duration,
+    window: Duration,
+    max_per_window: usize,
+    last_shown: HashMap<String, Instant>,
+    shown_in_window: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(dedup_window: Duration, window: Duration, max_per_window: usize) -> Self {
+        RateLimiter {
+            dedup_window,
+            window,
+            max_per_window,
+            last_shown: HashMap::new(),
+            shown_in_window: VecDeque::new(),
+        }
+    }
+
+    /// Returns whether a report identified by `key` may be shown at `now`, recording it if so.
+    pub fn allow(&mut self, key: &str, now: Instant) -> bool {
+        while let Some(oldest) = self.shown_in_window.front() {
+            if now.duration_since(*oldest) >= self.window {
+                self.shown_in_window.pop_front();
+            } else {
+                break;
+            }
+        }
+        self.last_shown.retain(|_, shown| now.duration_since(*shown) < self.dedup_window);
+
+        if self.last_shown.contains_key(key) || self.shown_in_window.len() >= self.max_per_window {
+            return false;
+        }
+        self.last_shown.insert(key.to_string(), now);
+        self.shown_in_window.push_back(now);
+        true
+    }
+}
+
+struct Notifier {
+    policy: NotifyPolicy,
+    limiter: RateLimiter,
+    // Notifications are shown from a separate thread, as showing one can take a moment.
+    toast_sender: mpsc::Sender<(String, String)>,
+}
+
+static NOTIFIER: OnceLock<Mutex<Notifier>> = OnceLock::new();
+
+/// Sets up reporting. Reports made before this are only logged.
+pub fn init(config: &NotificationsConfig) {
+    let (toast_sender, toast_receiver) = mpsc::channel::<(String, String)>();
+    thread::spawn(move || {
+        for (title, body) in toast_receiver {
+            if let Err(e) = notify_rust::Notification::new()
+                .appname(APP_NAME)
+                .summary(&title)
+                .body(&body)
+                .show()
+            {
+                warn!("Failed to show notification '{}': {:?}", title, e);
+            }
+        }
+    });
+
+    let notifier = Notifier {
+        policy: config.policy,
+        limiter: RateLimiter::new(
+            Duration::from_secs(config.dedup_seconds),
+            Duration::from_secs(60),
+            config.max_per_minute,
+        ),
+        toast_sender,
+    };
+    if NOTIFIER.set(Mutex::new(notifier)).is_err() {
+        warn!("Notifications were already initialized.");
+    }
+}
+
+impl Notifier {
+    fn report_error(&mut self, title: &str, body: &str, now: Instant) {
+        match self.policy {
+            NotifyPolicy::Silent => {
+                debug!("{}: {}", title, body);
+                return;
+            }
+            NotifyPolicy::LogOnly => {
+                error!("{}: {}", title, body);
+                return;
+            }
+            NotifyPolicy::Toast => error!("{}: {}", title, body),
+        }
+        let key = format!("{}\n{}", title, body);
+        if !self.limiter.allow(&key, now) {
+            debug!("Notification '{}' suppressed by rate limiting.", title);
+            return;
+        }
+        let _ = self.toast_sender.send((title.to_string(), body.to_string()));
+    }
+}
+
+/// Reports a failure the user should know about.
+pub fn report_error(title: &str, body: &str) {
+    let Some(notifier) = NOTIFIER.get() else {
+        error!("{}: {}", title, body);
+        return;
+    };
+    let mut notifier = notifier.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    notifier.report_error(title, body, Instant::now());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEDUP: Duration = Duration::from_secs(60);
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn suppresses_duplicates_within_the_dedup_window() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(DEDUP, WINDOW, 5);
+        assert!(limiter.allow("a", start));
+        assert!(!limiter.allow("a", start + secs(1)));
+        assert!(!limiter.allow("a", start + DEDUP - secs(1)));
+        // Other reports aren't held up by it.
+        assert!(limiter.allow("b", start + secs(1)));
+        assert!(limiter.allow("a", start + DEDUP));
+    }
+
+    #[test]
+    fn a_suppressed_duplicate_does_not_extend_the_dedup_window() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(secs(10), WINDOW, 5);
+        assert!(limiter.allow("a", start));
+        assert!(!limiter.allow("a", start + secs(9)));
+        assert!(limiter.allow("a", start + secs(10)));
+    }
+
+    #[test]
+    fn caps_bursts_per_window() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(secs(1), WINDOW, 3);
+        for (n, key) in ["a", "b", "c"].into_iter().enumerate() {
+            assert!(limiter.allow(key, start + secs(n as u64)), "{}", key);
+        }
+        assert!(!limiter.allow("d", start + secs(3)));
+        assert!(!limiter.allow("a", start + secs(30)));
+        // The first report leaves the window, making room for one more.
+        assert!(limiter.allow("d", start + WINDOW));
+        assert!(!limiter.allow("e", start + WINDOW));
+        assert!(limiter.allow("e", start + WINDOW + secs(1)));
+    }
+
+    fn with_policy(policy: NotifyPolicy) -> (Notifier, mpsc::Receiver<(String, String)>) {
+        let (toast_sender, toasts) = mpsc::channel();
+        let notifier = Notifier { policy, limiter: RateLimiter::new(DEDUP, WINDOW, 5), toast_sender };
+        (notifier, toasts)
+    }
+
+    #[test]
+    fn toasts_only_under_the_toast_policy() {
+        let now = Instant::now();
+        let (mut notifier, toasts) = with_policy(NotifyPolicy::Toast);
+        notifier.report_error("Title", "body", now);
+        notifier.report_error("Title", "body", now);
+        assert_eq!(toasts.try_iter().collect::<Vec<_>>(), [("Title".to_string(), "body".to_string())]);
+
+        for policy in [NotifyPolicy::LogOnly, NotifyPolicy::Silent] {
+            let (mut notifier, toasts) = with_policy(policy);
+            notifier.report_error("Title", "body", now);
+            assert_eq!(toasts.try_recv(), Err(mpsc::TryRecvError::Empty), "{:?}", policy);
+            // Nothing shown, so nothing counts towards the limits.
+            assert!(notifier.limiter.allow("Title\nbody", now), "{:?}", policy);
+        }
+    }
+}
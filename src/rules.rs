@@ -0,0 +1,315 @@
+// src/rules.rs
+
+//! Window rules: new windows whose executable, class and title match a rule are moved to
+//! the rule's desktop, or pinned to all desktops. A rule sees nothing of a window but
+//! those three strings, so it can be checked against made-up windows as easily as against
+//! the ones `new_windows` reports.
+//!
+//! A pattern is a glob (`*` any run of characters, `?` any one character) matched against
+//! the whole text ignoring case, or a regular expression when written between slashes,
+//! e.g. `/^Inbox - .* - Outlook$/`. Regular expressions match anywhere in the text and are
+//! case-sensitive unless they start with `(?i)`.
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::command::DesktopTarget;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Pattern {
+    /// A lowercased glob.
+    Glob(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn matches(&self, text: &str) -> bool {
+        match self {
+            Pattern::Glob(glob) => glob_matches(glob, &text.to_lowercase()),
+            Pattern::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+// Regexes don't compare; two patterns are the same if they were written the same.
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Pattern::Glob(a), Pattern::Glob(b)) => a == b,
+            (Pattern::Regex(a), Pattern::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Pattern {}
+
+impl TryFrom<String> for Pattern {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.strip_prefix('/').and_then(|rest| rest.strip_suffix('/')) {
+            Some(expression) => Regex::new(expression)
+                .map(Pattern::Regex)
+                .map_err(|e| format!("invalid regular expression '{}': {}", value, e)),
+            None => Ok(Pattern::Glob(value.to_lowercase())),
+        }
+    }
+}
+
+// Whether `text` as a whole matches `glob`. Backtracks only to the last `*`, which is
+// enough because a later `*` can absorb anything an earlier one could.
+fn glob_matches(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut g, mut t) = (0, 0);
+    // Position after the last `*` seen, and where in the text it started matching.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g + 1, t));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                g += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the `*` take one more character and try again from there.
+                Some((after_star, start)) => {
+                    star = Some((after_star, start + 1));
+                    g = after_star;
+                    t = start + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+/// What a rule is matched against.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WindowProperties {
+    /// File name of the process image, e.g. "firefox.exe".
+    pub exe: String,
+    pub class: String,
+    pub title: String,
+}
+
+/// Patterns for a window's executable, class and title. Missing ones match anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WindowMatcher {
+    pub exe: Option<Pattern>,
+    pub class: Option<Pattern>,
+    pub title: Option<Pattern>,
+}
+
+impl WindowMatcher {
+    /// Whether every pattern there is matches the window.
+    pub fn matches(&self, window: &WindowProperties) -> bool {
+        let fits = |pattern: &Option<Pattern>, text: &str| pattern.as_ref().is_none_or(|p| p.matches(text));
+        fits(&self.exe, &window.exe) && fits(&self.class, &window.class) && fits(&self.title, &window.title)
+    }
+
+    /// Whether there is no pattern at all, so every window would match.
+    pub fn is_empty(&self) -> bool {
+        self.exe.is_none() && self.class.is_none() && self.title.is_none()
+    }
+}
+
+/// A rule as written in the config file, e.g.
+/// `{ exe = "slack.exe", desktop = "chat" }`, `{ title = "/ - Outlook$/", desktop = 2, follow = true }`
+/// or `{ exe = "spotify.exe", pin = true }`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RuleConfig")]
+pub struct WindowRule {
+    pub matcher: WindowMatcher,
+    pub placement: Placement,
+}
+
+/// Where a rule puts matching windows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Placement {
+    /// Moves the window to the desktop; `follow` switches there along with it.
+    Desktop { desktop: DesktopTarget, follow: bool },
+    /// Shows the window on every desktop.
+    Pinned,
+}
+
+/// The first rule that matches the window, if any.
+pub fn find_rule<'a>(rules: &'a [WindowRule], window: &WindowProperties) -> Option<&'a WindowRule> {
+    rules.iter().find(|rule| rule.matcher.matches(window))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    exe: Option<Pattern>,
+    class: Option<Pattern>,
+    title: Option<Pattern>,
+    desktop: Option<RuleDesktop>,
+    #[serde(default)]
+    follow: bool,
+    #[serde(default)]
+    pin: bool,
+}
+
+/// A desktop in the config file: a 1-based number, or text as on the command line.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum RuleDesktop {
+    Number(usize),
+    Text(String),
+}
+
+impl RuleDesktop {
+    pub fn target(self) -> Result<DesktopTarget, String> {
+        match self {
+            RuleDesktop::Number(number) => number.to_string().parse(),
+            RuleDesktop::Text(text) => text.parse(),
+        }
+    }
+}
+
+impl TryFrom<RuleConfig> for WindowRule {
+    type Error = String;
+
+    fn try_from(config: RuleConfig) -> Result<Self, Self::Error> {
+        let matcher = WindowMatcher { exe: config.exe, class: config.class, title: config.title };
+        if matcher.is_empty() {
+            return Err("a window rule needs at least one of exe, class or title".to_string());
+        }
+        let desktop = config.desktop.map(RuleDesktop::target).transpose()?;
+        let placement = match (desktop, config.pin) {
+            (Some(desktop), false) => Placement::Desktop { desktop, follow: config.follow },
+            (None, true) if !config.follow => Placement::Pinned,
+            (None, true) => return Err("a pinned window is on every desktop; there is nothing to follow".to_string()),
+            (Some(_), true) => return Err("a window rule can't both pin a window and give it a desktop".to_string()),
+            (None, false) => return Err("a window rule needs a desktop or pin = true".to_string()),
+        };
+        Ok(WindowRule { matcher, placement })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(text: &str) -> Pattern {
+        Pattern::try_from(text.to_string()).unwrap()
+    }
+
+    fn rule(toml_text: &str) -> Result<WindowRule, String> {
+        toml::from_str(toml_text).map_err(|e| e.to_string())
+    }
+
+    fn window(exe: &str, class: &str, title: &str) -> WindowProperties {
+        WindowProperties { exe: exe.to_string(), class: class.to_string(), title: title.to_string() }
+    }
+
+    #[test]
+    fn globs_match_the_whole_text() {
+        let cases = [
+            ("a*b*c", "aXbYbc", true),
+            ("a*b*c", "abc", true),
+            ("a*b*c", "aXbYbcd", false),
+            ("a*c", "abcbc", true),
+            ("*.exe", "firefox.exe", true),
+            ("*.exe", "firefox.exe.lnk", false),
+            ("fire?ox.exe", "firefox.exe", true),
+            ("fire?ox.exe", "fireox.exe", false),
+            ("*", "", true),
+            ("a*", "", false),
+            ("", "", true),
+        ];
+        for (glob, text, expected) in cases {
+            assert_eq!(pattern(glob).matches(text), expected, "{:?} against {:?}", glob, text);
+        }
+    }
+
+    #[test]
+    fn globs_ignore_case() {
+        assert!(pattern("Firefox.EXE").matches("firefox.exe"));
+        assert!(pattern("*mozilla*").matches("MozillaWindowClass"));
+    }
+
+    #[test]
+    fn regexes_are_written_between_slashes() {
+        let outlook = pattern("/^Inbox - .* - Outlook$/");
+        assert!(matches!(outlook, Pattern::Regex(_)));
+        assert!(outlook.matches("Inbox - me@example.com - Outlook"));
+        // Case-sensitive unless asked otherwise.
+        assert!(!outlook.matches("inbox - me@example.com - outlook"));
+        assert!(pattern("/(?i)outlook/").matches("Inbox - Outlook"));
+        // Not anchored unless asked to be.
+        assert!(pattern("/Outlook/").matches("Inbox - Outlook - Work"));
+        // A lone slash is a glob.
+        assert_eq!(pattern("/"), Pattern::Glob("/".to_string()));
+        assert!(Pattern::try_from("/(/".to_string()).unwrap_err().contains("invalid regular expression"));
+    }
+
+    #[test]
+    fn missing_patterns_match_anything() {
+        let matcher = WindowMatcher { class: Some(pattern("Chrome_WidgetWin_1")), ..WindowMatcher::default() };
+        assert!(matcher.matches(&window("slack.exe", "Chrome_WidgetWin_1", "Slack")));
+        assert!(!matcher.matches(&window("slack.exe", "Other", "Slack")));
+        assert!(WindowMatcher::default().is_empty());
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = [
+            rule(r#"exe = "firefox.exe"
+                    title = "*YouTube*"
+                    pin = true"#)
+            .unwrap(),
+            rule(r#"exe = "firefox.exe"
+                    desktop = 2"#)
+            .unwrap(),
+            rule(r#"title = "*"
+                    desktop = "misc""#)
+            .unwrap(),
+        ];
+        let placement = |window: &WindowProperties| find_rule(&rules, window).map(|rule| rule.placement.clone());
+        assert_eq!(placement(&window("firefox.exe", "MozillaWindowClass", "Music - YouTube")), Some(Placement::Pinned));
+        assert_eq!(
+            placement(&window("firefox.exe", "MozillaWindowClass", "Inbox")),
+            Some(Placement::Desktop { desktop: DesktopTarget::Index(1), follow: false })
+        );
+        assert_eq!(
+            placement(&window("notepad.exe", "Notepad", "notes.txt")),
+            Some(Placement::Desktop { desktop: DesktopTarget::Name("misc".to_string()), follow: false })
+        );
+        assert!(find_rule(&rules[..2], &window("notepad.exe", "Notepad", "notes.txt")).is_none());
+    }
+
+    #[test]
+    fn reads_rules_from_the_config_file() {
+        let followed = rule(r#"title = "/ - Outlook$/"
+                               desktop = "new"
+                               follow = true"#)
+        .unwrap();
+        assert_eq!(followed.placement, Placement::Desktop { desktop: DesktopTarget::New, follow: true });
+        assert_eq!(followed.matcher.title, Some(pattern("/ - Outlook$/")));
+    }
+
+    #[test]
+    fn rejects_rules_that_make_no_sense() {
+        let cases = [
+            (r#"desktop = 2"#, "needs at least one of exe, class or title"),
+            (r#"exe = "a.exe""#, "needs a desktop or pin = true"),
+            ("exe = \"a.exe\"\ndesktop = 2\npin = true", "can't both pin a window and give it a desktop"),
+            ("exe = \"a.exe\"\npin = true\nfollow = true", "nothing to follow"),
+            ("exe = \"a.exe\"\ndesktop = 0", "invalid desktop '0'"),
+            ("exe = \"/(/\"\ndesktop = 1", "invalid regular expression"),
+            ("exe = \"a.exe\"\ndesktop = 1\nworkspace = 2", "unknown field"),
+        ];
+        for (toml_text, message) in cases {
+            let error = rule(toml_text).unwrap_err();
+            assert!(error.contains(message), "{:?} gave {:?}", toml_text, error);
+        }
+    }
+}
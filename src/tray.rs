@@ -0,0 +1,262 @@
+// src/tray.rs
+
+use anyhow::{Context, Result};
+use log::info;
+use tray_icon::{
+    menu::{accelerator::Accelerator, CheckMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu},
+    TrayIcon, TrayIconBuilder,
+};
+
+use crate::command::{Command, DesktopTarget};
+use crate::config::{TrayIconConfig, TrayIconLabel};
+use crate::icon::{dim, render_label_icon, renderable_label, IconStyle};
+use crate::DesktopInfo;
+
+const TRAY_ICON_TOOLTIP: &str = "DeskHopper";
+const MENU_ID_ABOUT_STR: &str = "about";
+const MENU_ID_EXIT_STR: &str = "exit";
+const MENU_ID_PAUSE_STR: &str = "pause";
+const MENU_ID_HOTKEY_STATUS: &str = "hotkeys";
+// Desktop entries use "switch:<0-based index>" as their menu ID.
+const MENU_ID_SWITCH_PREFIX: &str = "switch:";
+// Entries of the "Move active window to" submenu use "move:<0-based index>".
+const MENU_ID_MOVE_PREFIX: &str = "move:";
+const MENU_ID_MOVE_NEW: &str = "move:new";
+// Names are cut shorter than numbers so the letters stay readable at tray size.
+const NAME_LABEL_CHARS: usize = 2;
+
+const ICON_BYTES: &[u8] = include_bytes!("../icon.ico");
+
+/// Owns the tray icon and its context menu. The desktop entries at the top of
+/// the menu and in the move submenu are rebuilt whenever the set of virtual
+/// desktops changes.
+pub struct Tray {
+    icon: TrayIcon,
+    menu: Menu,
+    desktop_items: Vec<CheckMenuItem>,
+    move_submenu: Submenu,
+    move_items: Vec<MenuItem>,
+    pause_item: CheckMenuItem,
+    hotkey_status_item: MenuItem,
+    icon_label: TrayIconLabel,
+    icon_style: IconStyle,
+    current_desktop: Option<DesktopInfo>,
+    paused: bool,
+    mode: Option<String>,
+    // Label and paused state currently drawn on the icon, so unchanged icons aren't re-rendered.
+    shown_icon: Option<(Option<String>, bool)>,
+}
+
+fn load_icon_image() -> Result<image::RgbaImage> {
+    Ok(image::load_from_memory_with_format(ICON_BYTES, image::ImageFormat::Ico)
+        .context("Failed to load icon from memory")?
+        .to_rgba8())
+}
+
+fn to_tray_icon(image: image::RgbaImage) -> Result<tray_icon::Icon> {
+    let (width, height) = image.dimensions();
+    let icon_data = image.into_raw();
+    tray_icon::Icon::from_rgba(icon_data, width, height)
+        .context("Failed to create tray icon from RGBA data")
+}
+
+/// Text to draw on the tray icon for `desktop`, or `None` to keep the bundled icon.
+fn icon_label(desktop: &DesktopInfo, mode: TrayIconLabel) -> Option<String> {
+    let number = (desktop.index + 1).to_string();
+    match mode {
+        TrayIconLabel::Number => Some(number),
+        TrayIconLabel::Name => {
+            let from_name = desktop.name.as_deref().map(|name| renderable_label(name, NAME_LABEL_CHARS));
+            Some(from_name.filter(|label| !label.is_empty()).unwrap_or(number))
+        }
+        TrayIconLabel::None => None,
+    }
+}
+
+impl Tray {
+    pub fn new(icon_config: &TrayIconConfig) -> Result<Self> {
+        let icon_data = load_icon_image().and_then(to_tray_icon).context("Failed to load icon for tray")?;
+        let menu = Menu::new();
+
+        // MenuItem::with_id from tray_icon::menu (which is muda::MenuItem)
+        // takes (id, text, enabled, accelerator). The MenuEvent.id.0 will be the id string.
+        menu.append(&PredefinedMenuItem::separator()).context("Failed to append separator")?;
+
+        // Desktop entries are inserted ahead of the separator and "New desktop" item.
+        let move_submenu = Submenu::new("&Move active window to", true);
+        move_submenu.append(&PredefinedMenuItem::separator()).context("Failed to append separator")?;
+        let move_new_item = MenuItem::with_id(MENU_ID_MOVE_NEW, "&New desktop", true, None::<Accelerator>);
+        move_submenu.append(&move_new_item).context("Failed to append New desktop item")?;
+        menu.append(&move_submenu).context("Failed to append Move submenu")?;
+
+        let pause_item = CheckMenuItem::with_id(MENU_ID_PAUSE_STR, "&Pause hotkeys", true, false, None::<Accelerator>);
+        menu.append(&pause_item).context("Failed to append Pause item")?;
+        let hotkey_status_item = MenuItem::with_id(MENU_ID_HOTKEY_STATUS, "Hotkey &status...", true, None::<Accelerator>);
+        menu.append(&hotkey_status_item).context("Failed to append Hotkey status item")?;
+
+        menu.append(&PredefinedMenuItem::separator()).context("Failed to append separator")?;
+
+        let about_item = MenuItem::with_id(MENU_ID_ABOUT_STR, MENU_ID_ABOUT_STR, true, None::<Accelerator>);
+        menu.append(&about_item).context("Failed to append About item")?;
+
+        menu.append(&PredefinedMenuItem::separator()).context("Failed to append separator")?;
+
+        let exit_item = MenuItem::with_id(MENU_ID_EXIT_STR, MENU_ID_EXIT_STR, true, None::<Accelerator>);
+        menu.append(&exit_item).context("Failed to append Exit item")?;
+
+        // `Menu` is reference counted, so the clone handed to the tray icon and the one
+        // we keep refer to the same native menu.
+        let icon = TrayIconBuilder::new()
+            .with_menu(Box::new(menu.clone()))
+            .with_tooltip(TRAY_ICON_TOOLTIP)
+            .with_icon(icon_data)
+            .build()
+            .context("Failed to build system tray icon")?;
+
+        Ok(Tray {
+            icon,
+            menu,
+            desktop_items: Vec::new(),
+            move_submenu,
+            move_items: Vec::new(),
+            pause_item,
+            hotkey_status_item,
+            icon_label: icon_config.label,
+            icon_style: icon_config.style(),
+            current_desktop: None,
+            paused: false,
+            mode: None,
+            shown_icon: None,
+        })
+    }
+
+    /// Replaces the desktop entries of the menu with one checkable item per desktop,
+    /// checking the one at `current_idx`, and the entries of the move submenu with
+    /// one item per desktop.
+    pub fn set_desktops(&mut self, desktops: &[DesktopInfo], current_idx: Option<usize>) -> Result<()> {
+        for item in self.desktop_items.drain(..) {
+            self.menu.remove(&item).context("Failed to remove desktop item")?;
+        }
+        for item in self.move_items.drain(..) {
+            self.move_submenu.remove(&item).context("Failed to remove move item")?;
+        }
+
+        for (position, desktop) in desktops.iter().enumerate() {
+            let item = CheckMenuItem::with_id(
+                format!("{}{}", MENU_ID_SWITCH_PREFIX, desktop.index),
+                menu_label(desktop),
+                true,
+                current_idx == Some(desktop.index),
+                None::<Accelerator>,
+            );
+            self.menu.insert(&item, position).context("Failed to insert desktop item")?;
+            self.desktop_items.push(item);
+
+            let move_item = MenuItem::with_id(
+                format!("{}{}", MENU_ID_MOVE_PREFIX, desktop.index),
+                menu_label(desktop),
+                true,
+                None::<Accelerator>,
+            );
+            self.move_submenu.insert(&move_item, position).context("Failed to insert move item")?;
+            self.move_items.push(move_item);
+        }
+        info!("Tray menu updated with {} desktops (current: {:?}).", desktops.len(), current_idx);
+
+        if let Some(current) = current_idx.and_then(|idx| desktops.get(idx)) {
+            self.current_desktop = Some(current.clone());
+            self.update_icon()?;
+        }
+        Ok(())
+    }
+
+    /// Reflects whether hotkeys are paused in the menu check mark and the icon.
+    pub fn set_paused(&mut self, paused: bool) -> Result<()> {
+        self.paused = paused;
+        self.pause_item.set_checked(paused);
+        self.update_icon()
+    }
+
+    /// Points out unavailable hotkeys on the hotkey status item.
+    pub fn set_hotkey_failures(&mut self, failed: usize) {
+        if failed == 0 {
+            self.hotkey_status_item.set_text("Hotkey &status...");
+        } else {
+            self.hotkey_status_item.set_text(format!("Hotkey &status ({} unavailable)...", failed));
+        }
+    }
+
+    /// Shows the active mode, if any, in the tooltip.
+    pub fn set_mode(&mut self, mode: Option<&str>) -> Result<()> {
+        self.mode = mode.map(str::to_string);
+        self.update_tooltip()
+    }
+
+    fn update_icon(&mut self) -> Result<()> {
+        // The tooltip names the desktop, whose name can change while its label doesn't.
+        self.update_tooltip()?;
+        let label = self.current_desktop.as_ref().and_then(|desktop| icon_label(desktop, self.icon_label));
+        let wanted = (label, self.paused);
+        if self.shown_icon.as_ref() == Some(&wanted) {
+            return Ok(());
+        }
+
+        let mut image = match &wanted.0 {
+            Some(label) => render_label_icon(label, &self.icon_style),
+            None => load_icon_image()?,
+        };
+        if self.paused {
+            dim(&mut image);
+        }
+        self.icon.set_icon(Some(to_tray_icon(image)?)).context("Failed to set tray icon")?;
+
+        info!("Tray icon now shows {:?} (paused: {}).", wanted.0, wanted.1);
+        self.shown_icon = Some(wanted);
+        Ok(())
+    }
+
+    fn update_tooltip(&self) -> Result<()> {
+        let mut tooltip = match &self.current_desktop {
+            Some(desktop) => format!("{} - {}", TRAY_ICON_TOOLTIP, desktop.display_name()),
+            None => TRAY_ICON_TOOLTIP.to_string(),
+        };
+        if self.paused {
+            tooltip.push_str(" (hotkeys paused)");
+        }
+        if let Some(mode) = &self.mode {
+            tooltip.push_str(&format!(" [{} mode]", mode));
+        }
+        self.icon.set_tooltip(Some(tooltip)).context("Failed to set tray tooltip")
+    }
+}
+
+/// Returns the command for a menu item ID, if it belongs to one of our items.
+pub fn menu_command(id: &str) -> Option<Command> {
+    let command = match id {
+        MENU_ID_ABOUT_STR => Command::ShowAbout,
+        MENU_ID_EXIT_STR => Command::Exit,
+        MENU_ID_PAUSE_STR => Command::TogglePauseHotkeys,
+        MENU_ID_HOTKEY_STATUS => Command::ShowHotkeyStatus,
+        MENU_ID_MOVE_NEW => Command::MoveActiveWindow(DesktopTarget::New),
+        _ => {
+            if let Some(idx) = id.strip_prefix(MENU_ID_SWITCH_PREFIX) {
+                Command::SwitchDesktop(DesktopTarget::Index(idx.parse().ok()?))
+            } else if let Some(idx) = id.strip_prefix(MENU_ID_MOVE_PREFIX) {
+                Command::MoveActiveWindow(DesktopTarget::Index(idx.parse().ok()?))
+            } else {
+                return None;
+            }
+        }
+    };
+    Some(command)
+}
+
+fn menu_label(desktop: &DesktopInfo) -> String {
+    // Escape '&' so it isn't taken as a mnemonic marker.
+    let name = desktop.display_name().replace('&', "&&");
+    if desktop.index < 9 {
+        format!("&{}: {}", desktop.index + 1, name)
+    } else {
+        format!("{}: {}", desktop.index + 1, name)
+    }
+}
@@ -0,0 +1,205 @@
+// src/window.rs
+
+//! Inspection of top-level windows: listing them, their class, title and executable, and
+//! whether the virtual desktop API can move them at all. Used to explain a failed move
+//! instead of reporting a raw COM error, and to match windows against window rules. Also
+//! installs the hooks through which Windows reports changes to windows.
+
+use anyhow::Result;
+use std::{ffi::OsString, os::windows::ffi::OsStringExt, path::Path};
+use windows::core::PWSTR;
+use windows::Win32::{
+    Foundation::{CloseHandle, BOOL, E_ACCESSDENIED, HANDLE, HMODULE, HWND, LPARAM, TRUE},
+    Graphics::Dwm::{DwmGetWindowAttribute, DWMWA_CLOAKED},
+    Security::{GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY},
+    System::Threading::{
+        GetCurrentProcess, OpenProcess, OpenProcessToken, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    },
+    UI::{
+        Accessibility::{SetWinEventHook, WINEVENTPROC},
+        WindowsAndMessaging::{
+            EnumWindows, GetClassNameW, GetShellWindow, GetWindow, GetWindowLongW, GetWindowTextLengthW,
+            GetWindowTextW, GetWindowThreadProcessId, IsWindowVisible, GWL_STYLE, GW_OWNER, WINEVENT_OUTOFCONTEXT,
+            WINEVENT_SKIPOWNPROCESS, WS_CHILD,
+        },
+    },
+};
+
+use crate::error::NotMovableReason;
+use crate::rules::WindowProperties;
+
+// Windows owned by the shell itself, which never belong to a virtual desktop: the taskbar
+// and notification area, then the desktop background.
+const SYSTEM_WINDOW_CLASSES: &[&str] = &[
+    "Shell_TrayWnd",
+    "Shell_SecondaryTrayWnd",
+    "NotifyIconOverflowWindow",
+    "TopLevelWindowForOverflowXamlIsland",
+    "Progman",
+    "WorkerW",
+];
+// The taskbar and notification area alone, which take the foreground when the tray is used.
+const TASKBAR_WINDOW_CLASSES: &[&str] = SYSTEM_WINDOW_CLASSES.split_at(4).0;
+
+/// Has `callback` called whenever `event` happens to a window of another process. Out of
+/// context, the callback runs on the thread installing the hook, which must pump messages
+/// (the event loop thread). The hook stays installed for the lifetime of the process.
+pub fn install_event_hook(event: u32, callback: WINEVENTPROC) -> Result<()> {
+    let hook = unsafe {
+        SetWinEventHook(
+            event,
+            event,
+            HMODULE::default(),
+            callback,
+            0,
+            0,
+            WINEVENT_OUTOFCONTEXT | WINEVENT_SKIPOWNPROCESS,
+        )
+    };
+    if hook.is_invalid() {
+        anyhow::bail!("SetWinEventHook for event {:#06x} failed", event);
+    }
+    Ok(())
+}
+
+/// The visible top-level windows with a title, on any desktop, in Z order.
+pub fn top_level_windows() -> Vec<HWND> {
+    let mut windows: Vec<HWND> = Vec::new();
+    let _ = unsafe { EnumWindows(Some(collect_top_level_window), LPARAM(&mut windows as *mut _ as isize)) };
+    windows
+}
+
+extern "system" fn collect_top_level_window(hwnd: HWND, lparam: LPARAM) -> BOOL {
+    let windows = unsafe { &mut *(lparam.0 as *mut Vec<HWND>) };
+    if unsafe { IsWindowVisible(hwnd) }.as_bool() && is_top_level(hwnd) && unsafe { GetWindowTextLengthW(hwnd) } > 0 {
+        windows.push(hwnd);
+    }
+    TRUE
+}
+
+pub fn class_name(hwnd: HWND) -> String {
+    let mut class_buffer: [u16; 256] = [0; 256];
+    let len = unsafe { GetClassNameW(hwnd, &mut class_buffer) } as usize;
+    OsString::from_wide(&class_buffer[..len]).to_string_lossy().into_owned()
+}
+
+pub fn title(hwnd: HWND) -> String {
+    let len = unsafe { GetWindowTextLengthW(hwnd) };
+    if len <= 0 {
+        return String::new();
+    }
+    let mut title_buffer = vec![0u16; len as usize + 1];
+    let copied = unsafe { GetWindowTextW(hwnd, &mut title_buffer) } as usize;
+    OsString::from_wide(&title_buffer[..copied]).to_string_lossy().into_owned()
+}
+
+/// File name of the executable that owns the window, e.g. "firefox.exe".
+pub fn exe_name(hwnd: HWND) -> Option<String> {
+    let mut process_id: u32 = 0;
+    unsafe { GetWindowThreadProcessId(hwnd, Some(&mut process_id)) };
+    if process_id == 0 {
+        return None;
+    }
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id) }.ok()?;
+    let mut path_buffer: [u16; 1024] = [0; 1024];
+    let mut len = path_buffer.len() as u32;
+    let result =
+        unsafe { QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(path_buffer.as_mut_ptr()), &mut len) };
+    let _ = unsafe { CloseHandle(process) };
+    result.ok()?;
+    let path = OsString::from_wide(&path_buffer[..len as usize]);
+    Some(Path::new(&path).file_name()?.to_string_lossy().into_owned())
+}
+
+/// What window rules are matched against. Parts that can't be read are left empty.
+pub fn properties(hwnd: HWND) -> WindowProperties {
+    WindowProperties { exe: exe_name(hwnd).unwrap_or_default(), class: class_name(hwnd), title: title(hwnd) }
+}
+
+/// Whether the window is a dialog or tool window owned by another window, which goes
+/// wherever its owner is.
+pub fn is_owned(hwnd: HWND) -> bool {
+    unsafe { GetWindow(hwnd, GW_OWNER) }.is_ok_and(|owner| !owner.is_invalid())
+}
+
+/// Whether the window is a top-level window, rather than a child control.
+pub fn is_top_level(hwnd: HWND) -> bool {
+    let style = unsafe { GetWindowLongW(hwnd, GWL_STYLE) } as u32;
+    (style & WS_CHILD.0) == 0
+}
+
+/// Whether the window is shown on every desktop, by itself or as part of its application.
+pub fn is_pinned(hwnd: HWND) -> bool {
+    winvd::is_pinned_window(hwnd).unwrap_or(false) || winvd::is_pinned_app(hwnd).unwrap_or(false)
+}
+
+/// Whether `hwnd` is part of the taskbar or the notification area.
+pub fn is_taskbar_window(hwnd: HWND) -> bool {
+    TASKBAR_WINDOW_CLASSES.contains(&class_name(hwnd).as_str())
+}
+
+/// Why moving `hwnd` to another desktop can't work, if it's one of the known cases.
+pub fn not_movable_reason(hwnd: HWND) -> Option<NotMovableReason> {
+    if hwnd == unsafe { GetShellWindow() } || SYSTEM_WINDOW_CLASSES.contains(&class_name(hwnd).as_str()) {
+        return Some(NotMovableReason::System);
+    }
+    if is_cloaked(hwnd) {
+        return Some(NotMovableReason::Cloaked);
+    }
+    // An elevated window can't be moved by a process that isn't elevated itself.
+    if is_window_elevated(hwnd) && !is_process_elevated(unsafe { GetCurrentProcess() }).unwrap_or(false) {
+        return Some(NotMovableReason::Elevated);
+    }
+    None
+}
+
+// Cloaked windows are hidden by DWM, e.g. suspended UWP apps and windows of other desktops.
+fn is_cloaked(hwnd: HWND) -> bool {
+    let mut cloaked: u32 = 0;
+    let result = unsafe {
+        DwmGetWindowAttribute(
+            hwnd,
+            DWMWA_CLOAKED,
+            &mut cloaked as *mut u32 as *mut _,
+            std::mem::size_of::<u32>() as u32,
+        )
+    };
+    result.is_ok() && cloaked != 0
+}
+
+fn is_window_elevated(hwnd: HWND) -> bool {
+    let mut process_id: u32 = 0;
+    unsafe { GetWindowThreadProcessId(hwnd, Some(&mut process_id)) };
+    if process_id == 0 {
+        return false;
+    }
+    let Ok(process) = (unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id) }) else {
+        return false;
+    };
+    let elevated = is_process_elevated(process);
+    let _ = unsafe { CloseHandle(process) };
+    // Being refused the token of a process we could open means it runs at a higher integrity level.
+    elevated.unwrap_or(true)
+}
+
+// None if the process token can't be read.
+fn is_process_elevated(process: HANDLE) -> Option<bool> {
+    let mut token = HANDLE::default();
+    if let Err(e) = unsafe { OpenProcessToken(process, TOKEN_QUERY, &mut token) } {
+        return if e.code() == E_ACCESSDENIED { None } else { Some(false) };
+    }
+    let mut elevation = TOKEN_ELEVATION::default();
+    let mut returned: u32 = 0;
+    let result = unsafe {
+        GetTokenInformation(
+            token,
+            TokenElevation,
+            Some(&mut elevation as *mut TOKEN_ELEVATION as *mut _),
+            std::mem::size_of::<TOKEN_ELEVATION>() as u32,
+            &mut returned,
+        )
+    };
+    let _ = unsafe { CloseHandle(token) };
+    Some(result.is_ok() && elevation.TokenIsElevated != 0)
+}